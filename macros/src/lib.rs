@@ -0,0 +1,140 @@
+//! Attribute macros for the [`catch-unwind`](https://docs.rs/catch-unwind) crate. Use them
+//! through the `macros` feature of that crate instead of depending on this crate directly.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{meta::ParseNestedMeta, parse_macro_input, Error, ItemFn, LitStr, Path, ReturnType};
+
+/// Parse a `crate = "path"` argument, for using the macros when the `catch-unwind` dependency
+/// is renamed
+fn parse_crate(meta: &ParseNestedMeta, krate: &mut Option<Path>) -> syn::Result<()> {
+    if krate.is_some() {
+        return Err(meta.error("duplicate `crate` argument"));
+    }
+    *krate = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+    Ok(())
+}
+
+fn crate_path(krate: Option<Path>) -> Path {
+    krate.unwrap_or_else(|| syn::parse_quote!(::catch_unwind))
+}
+
+/// Wrap the body of an `extern "C"` or `extern "system"` function in
+/// `catch_unwind::ffi::ffi_boundary`, so a panic returns the error value of the function's
+/// return type instead of unwinding.
+///
+/// Use `#[ffi_catch_unwind(record)]` to store the payload for `take_last_panic` instead of
+/// dropping it. If the `catch-unwind` dependency is renamed, pass its path with
+/// `#[ffi_catch_unwind(crate = "path")]`.
+#[proc_macro_attribute]
+pub fn ffi_catch_unwind(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut record = false;
+    let mut krate = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("record") {
+            record = true;
+            Ok(())
+        } else if meta.path.is_ident("crate") {
+            parse_crate(&meta, &mut krate)
+        } else {
+            Err(meta.error("expected `record` or `crate`"))
+        }
+    });
+    parse_macro_input!(attr with parser);
+    let krate = crate_path(krate);
+    let boundary = if record {
+        quote!(ffi_boundary_recorded)
+    } else {
+        quote!(ffi_boundary)
+    };
+    let mut item = parse_macro_input!(item as ItemFn);
+    match &item.sig.abi {
+        None => {
+            return Error::new_spanned(
+                &item.sig,
+                "`ffi_catch_unwind` can only be used on `extern` functions",
+            )
+            .into_compile_error()
+            .into()
+        }
+        Some(abi) => {
+            let name = abi.name.as_ref().map(LitStr::value);
+            if !matches!(name.as_deref(), None | Some("C" | "system")) {
+                return Error::new_spanned(
+                    abi,
+                    "`ffi_catch_unwind` can only be used on `extern \"C\"` or \
+                     `extern \"system\"` functions",
+                )
+                .into_compile_error()
+                .into();
+            }
+        }
+    }
+    if let Some(asyncness) = &item.sig.asyncness {
+        return Error::new_spanned(
+            asyncness,
+            "`ffi_catch_unwind` can't be used on async functions",
+        )
+        .into_compile_error()
+        .into();
+    }
+
+    let ret = match &item.sig.output {
+        ReturnType::Default => quote!(()),
+        ReturnType::Type(_, ty) => quote!(#ty),
+    };
+    let body = &item.block;
+    *item.block = syn::parse_quote!({
+        #krate::ffi::#boundary(::core::panic::AssertUnwindSafe(move || -> #ret #body))
+    });
+    quote!(#item).into()
+}
+
+/// Wrap the body of a function in `catch_unwind::catch_unwind_wrapped`, so a panic is returned
+/// as the error of the function's `Result` return type instead of unwinding. The error type must
+/// implement `From<catch_unwind::Payload>`.
+///
+/// If the `catch-unwind` dependency is renamed, pass its path with
+/// `#[catch_unwind(crate = "path")]`.
+#[proc_macro_attribute]
+pub fn catch_unwind(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut krate = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("crate") {
+            parse_crate(&meta, &mut krate)
+        } else {
+            Err(meta.error("expected `crate`"))
+        }
+    });
+    parse_macro_input!(attr with parser);
+    let krate = crate_path(krate);
+    let mut item = parse_macro_input!(item as ItemFn);
+    if let Some(asyncness) = &item.sig.asyncness {
+        return Error::new_spanned(asyncness, "`catch_unwind` can't be used on async functions")
+            .into_compile_error()
+            .into();
+    }
+    let ret = match &item.sig.output {
+        ReturnType::Default => {
+            return Error::new_spanned(
+                &item.sig,
+                "`catch_unwind` can only be used on functions that return a `Result`",
+            )
+            .into_compile_error()
+            .into()
+        }
+        ReturnType::Type(_, ty) => quote!(#ty),
+    };
+    let body = &item.block;
+    *item.block = syn::parse_quote!({
+        match #krate::catch_unwind_wrapped(
+            ::core::panic::AssertUnwindSafe(move || -> #ret #body),
+        ) {
+            ::core::result::Result::Ok(ret) => ret,
+            ::core::result::Result::Err(payload) => {
+                ::core::result::Result::Err(::core::convert::From::from(payload))
+            }
+        }
+    });
+    quote!(#item).into()
+}
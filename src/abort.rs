@@ -0,0 +1,286 @@
+//! Aborting the process
+
+use crate::rt;
+use std::{
+    marker::PhantomData,
+    mem,
+    panic::{catch_unwind, AssertUnwindSafe, Location},
+    process, ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicI32, AtomicPtr, AtomicU8, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+type AbortCallback = Box<dyn Fn() + Send + Sync>;
+
+static ABORT_CALLBACKS: Mutex<Vec<AbortCallback>> = Mutex::new(Vec::new());
+static ABORTING: AtomicBool = AtomicBool::new(false);
+
+static TERMINATOR: TerminatorCell = TerminatorCell::new();
+
+const TERMINATE_ABORT: u8 = 0;
+const TERMINATE_EXIT: u8 = 1;
+const TERMINATE_CUSTOM: u8 = 2;
+
+/// How this crate terminates the process when it has to abort
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Terminator {
+    /// Call [`std::process::abort`]. This is the default.
+    #[default]
+    Abort,
+
+    /// Exit with the given code using [`std::process::exit`]
+    Exit(i32),
+
+    /// Call a user function, e.g. one that calls `libc::_exit` or raises a signal. If the
+    /// function panics, the process is aborted with [`std::process::abort`].
+    Custom(fn() -> !),
+}
+
+/// Terminator kept in atomics, so it can be read while aborting without locking
+struct TerminatorCell {
+    kind: AtomicU8,
+    code: AtomicI32,
+    f: AtomicPtr<()>,
+}
+
+impl TerminatorCell {
+    const fn new() -> Self {
+        Self {
+            kind: AtomicU8::new(TERMINATE_ABORT),
+            code: AtomicI32::new(0),
+            f: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    fn load(&self) -> Terminator {
+        match self.kind.load(Ordering::Acquire) {
+            TERMINATE_EXIT => Terminator::Exit(self.code.load(Ordering::Relaxed)),
+            TERMINATE_CUSTOM => {
+                let f = self.f.load(Ordering::Relaxed);
+                // SAFETY: the pointer was stored from a `fn() -> !` before the kind was set
+                Terminator::Custom(unsafe { mem::transmute::<*mut (), fn() -> !>(f) })
+            }
+            _ => Terminator::Abort,
+        }
+    }
+
+    fn store(&self, terminator: Terminator) {
+        let kind = match terminator {
+            Terminator::Abort => TERMINATE_ABORT,
+            Terminator::Exit(code) => {
+                self.code.store(code, Ordering::Relaxed);
+                TERMINATE_EXIT
+            }
+            Terminator::Custom(f) => {
+                self.f.store(f as *mut (), Ordering::Relaxed);
+                TERMINATE_CUSTOM
+            }
+        };
+        self.kind.store(kind, Ordering::Release)
+    }
+}
+
+impl Terminator {
+    fn terminate(self) -> ! {
+        match self {
+            Self::Abort => process::abort(),
+            Self::Exit(code) => process::exit(code),
+            Self::Custom(f) => match catch_unwind(f) {
+                Err(err) => {
+                    mem::forget(err);
+                    process::abort()
+                }
+            },
+        }
+    }
+}
+
+/// Set how this crate terminates the process when it has to abort, after calling the callbacks
+/// registered with [`on_abort`]
+pub fn set_terminator(terminator: Terminator) {
+    TERMINATOR.store(terminator)
+}
+
+/// Get how this crate terminates the process when it has to abort. See [`set_terminator`].
+pub fn terminator() -> Terminator {
+    TERMINATOR.load()
+}
+
+/// Register a callback to be called before this crate aborts the process, e.g. to flush logs
+/// or notify a supervisor. Callbacks are called in the order they were registered.
+///
+/// Calling the callbacks is best-effort: a callback that panics is skipped, and the callbacks
+/// aren't called if the crate aborts again while they're running, or if a callback is being
+/// registered at the same time.
+///
+/// Apart from the callbacks, the abort path doesn't allocate, block on locks, or use buffered
+/// output, so the process dies cleanly even when aborting from inside an allocator or a signal
+/// handler. Callbacks that need to work in those contexts must keep to the same restrictions.
+pub fn on_abort(callback: impl Fn() + Send + Sync + 'static) {
+    ABORT_CALLBACKS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .push(Box::new(callback));
+}
+
+/// Guard that aborts the process if a panic unwinds while it's alive, for marking code that
+/// must not unwind. See also [`abort_on_panic!`](crate::abort_on_panic).
+///
+/// The process is aborted when the guard is dropped during unwinding, after writing the
+/// location the guard was created at to stderr. A guard created while the thread is already
+/// panicking, e.g. in a destructor, doesn't abort.
+#[must_use = "the guard only protects code that runs while it's alive"]
+pub struct AbortOnUnwind {
+    location: &'static Location<'static>,
+    panicking: bool,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl AbortOnUnwind {
+    /// Create a new guard
+    #[inline]
+    #[track_caller]
+    pub fn new() -> Self {
+        Self {
+            location: Location::caller(),
+            panicking: thread::panicking(),
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl Default for AbortOnUnwind {
+    #[inline]
+    #[track_caller]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for AbortOnUnwind {
+    #[inline]
+    fn drop(&mut self) {
+        if !self.panicking && thread::panicking() {
+            rt::log(format_args!(
+                "catch-unwind: panic unwound out of a no-unwind zone at {}",
+                self.location
+            ));
+            abort()
+        }
+    }
+}
+
+/// Run a block of statements as a no-unwind zone, aborting the process with the location of the
+/// block if a panic unwinds out of it. This is a block form of
+/// [`AbortOnUnwind`](crate::AbortOnUnwind).
+///
+/// ```
+/// use catch_unwind::abort_on_panic;
+///
+/// let mut values = vec![1, 2, 3];
+/// let sum: i32 = abort_on_panic! {
+///     values.push(4);
+///     values.iter().sum()
+/// };
+/// assert_eq!(sum, 10);
+/// ```
+#[macro_export]
+macro_rules! abort_on_panic {
+    ($($body:tt)*) => {{
+        let _guard = $crate::AbortOnUnwind::new();
+        $($body)*
+    }};
+}
+
+/// Call the registered abort callbacks, then terminate the process with the configured
+/// [`Terminator`]
+pub(crate) fn abort() -> ! {
+    let terminator = TERMINATOR.load();
+    run_abort_callbacks();
+    terminator.terminate()
+}
+
+/// Call the registered abort callbacks, then exit the process with `code`
+pub(crate) fn exit(code: i32) -> ! {
+    run_abort_callbacks();
+    process::exit(code)
+}
+
+fn run_abort_callbacks() {
+    run_callbacks(&ABORT_CALLBACKS, &ABORTING)
+}
+
+/// Call the callbacks in `callbacks`, unless `aborting` is already set
+fn run_callbacks(callbacks: &Mutex<Vec<AbortCallback>>, aborting: &AtomicBool) {
+    if !aborting.swap(true, Ordering::SeqCst) {
+        if let Ok(callbacks) = callbacks.try_lock() {
+            for callback in callbacks.iter() {
+                if let Err(err) = catch_unwind(AssertUnwindSafe(callback)) {
+                    // dropping the payload could panic again or allocate
+                    mem::forget(err);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_run_callbacks() {
+        static CALLBACKS: Mutex<Vec<AbortCallback>> = Mutex::new(Vec::new());
+        static ABORTING: AtomicBool = AtomicBool::new(false);
+        static CALLED: AtomicUsize = AtomicUsize::new(0);
+
+        let mut callbacks = CALLBACKS.lock().unwrap();
+        callbacks.push(Box::new(|| panic!("callback")));
+        callbacks.push(Box::new(|| {
+            CALLED.fetch_add(1, Ordering::Relaxed);
+            run_callbacks(&CALLBACKS, &ABORTING);
+        }));
+        drop(callbacks);
+        run_callbacks(&CALLBACKS, &ABORTING);
+        assert_eq!(CALLED.load(Ordering::Relaxed), 1);
+        run_callbacks(&CALLBACKS, &ABORTING);
+        assert_eq!(CALLED.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_abort_on_unwind() {
+        let guard = AbortOnUnwind::new();
+        assert_eq!(guard.location.file(), file!());
+        drop(guard);
+    }
+
+    #[test]
+    fn test_abort_on_panic() {
+        let value = abort_on_panic! {
+            let value = 1;
+            value + 1
+        };
+        assert_eq!(value, 2);
+    }
+
+    #[test]
+    fn test_terminator() {
+        let cell = TerminatorCell::new();
+        assert!(matches!(cell.load(), Terminator::Abort));
+        cell.store(Terminator::Exit(3));
+        assert!(matches!(cell.load(), Terminator::Exit(3)));
+        fn custom() -> ! {
+            process::abort()
+        }
+        cell.store(Terminator::Custom(custom));
+        assert!(
+            matches!(cell.load(), Terminator::Custom(f) if ptr::fn_addr_eq(f, custom as fn() -> !))
+        );
+        cell.store(Terminator::default());
+        assert!(matches!(cell.load(), Terminator::Abort));
+    }
+}
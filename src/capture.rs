@@ -0,0 +1,578 @@
+//! Capturing the location and backtrace of caught panics
+
+use crate::{
+    catch_unwind_wrapped,
+    hook::{chain_hook, SUPPRESS_DEPTH},
+    Payload, PayloadSummary,
+};
+use std::{
+    backtrace::Backtrace,
+    cell::{Cell, RefCell},
+    fmt,
+    marker::PhantomData,
+    panic::{AssertUnwindSafe, Location, UnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Once,
+    },
+    thread::{self, LocalKey},
+};
+
+static VERBOSE_DROPS: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static CAPTURE_DEPTH: Cell<usize> = const { Cell::new(0) };
+    static CAPTURED: RefCell<Option<Captured>> = const { RefCell::new(None) };
+    static ASSERTION: Cell<Option<UnwindSafetyAssertion>> = const { Cell::new(None) };
+}
+
+struct Captured {
+    payload: PayloadSummary,
+    location: Option<PanicLocation>,
+    backtrace: Backtrace,
+    thread: Option<Box<str>>,
+    assertion: Option<Box<UnwindSafetyAssertion>>,
+}
+
+/// Source location of a panic
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PanicLocation {
+    file: String,
+    line: u32,
+    column: u32,
+}
+
+impl PanicLocation {
+    /// Get the name of the source file the panic originated from
+    #[inline]
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    /// Get the line number the panic originated from
+    #[inline]
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// Get the column the panic originated from
+    #[inline]
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+}
+
+impl From<&Location<'_>> for PanicLocation {
+    #[inline]
+    fn from(location: &Location<'_>) -> Self {
+        Self {
+            file: location.file().into(),
+            line: location.line(),
+            column: location.column(),
+        }
+    }
+}
+
+impl fmt::Display for PanicLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// Unwind safety assertion made with [`assert_unwind_safe`] or [`assert_unwind_safe_because`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct UnwindSafetyAssertion {
+    location: &'static Location<'static>,
+    justification: Option<&'static str>,
+}
+
+impl UnwindSafetyAssertion {
+    /// Get the location the assertion was made at
+    #[inline]
+    pub fn location(&self) -> &'static Location<'static> {
+        self.location
+    }
+
+    /// Get the justification for the assertion, if one was given
+    #[inline]
+    pub fn justification(&self) -> Option<&'static str> {
+        self.justification
+    }
+}
+
+impl fmt::Display for UnwindSafetyAssertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unwind safety asserted at {}", self.location)?;
+        if let Some(justification) = self.justification {
+            write!(f, ": {justification}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Caught panic with its payload and the information captured when it occurred
+pub struct CaughtPanic {
+    payload: Payload,
+    location: Option<PanicLocation>,
+    backtrace: Option<Backtrace>,
+    thread: Option<Box<str>>,
+    assertion: Option<Box<UnwindSafetyAssertion>>,
+}
+
+impl CaughtPanic {
+    /// Get a reference to the payload
+    #[inline]
+    pub fn payload(&self) -> &Payload {
+        &self.payload
+    }
+
+    /// Get a mutable reference to the payload
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut Payload {
+        &mut self.payload
+    }
+
+    /// Get the payload, discarding the captured information
+    #[inline]
+    pub fn into_payload(self) -> Payload {
+        self.payload
+    }
+
+    /// Get the panic message, if the payload is a string
+    #[inline]
+    pub fn message(&self) -> Option<&str> {
+        self.payload.message()
+    }
+
+    /// Get the location the panic originated from, if it was captured
+    #[inline]
+    pub fn location(&self) -> Option<&PanicLocation> {
+        self.location.as_ref()
+    }
+
+    /// Get the backtrace of the panic, if it was captured. Whether a backtrace is actually
+    /// captured is controlled by the `RUST_BACKTRACE` and `RUST_LIB_BACKTRACE` environment
+    /// variables; see [`Backtrace::capture`].
+    #[inline]
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        self.backtrace.as_ref()
+    }
+
+    /// Get the name of the thread the panic occurred on, if it has one and it was captured
+    #[inline]
+    pub fn thread_name(&self) -> Option<&str> {
+        self.thread.as_deref()
+    }
+
+    /// Get the innermost unwind safety assertion that was active when the panic occurred, if it
+    /// was made with [`assert_unwind_safe`] or [`assert_unwind_safe_because`] and captured
+    #[inline]
+    pub fn unwind_safety_assertion(&self) -> Option<&UnwindSafetyAssertion> {
+        self.assertion.as_deref()
+    }
+
+    /// Wrap a caught payload with the information captured for it. The captured information is
+    /// only used if it was captured for a payload like this one, because a payload resumed with
+    /// `resume_unwind` doesn't run the panic hook, and the information may be left over from
+    /// another panic that was caught without taking it.
+    pub(crate) fn new(payload: Payload) -> Self {
+        let captured = take_captured().filter(|captured| captured.payload == payload.summary());
+        match captured {
+            Some(captured) => Self {
+                payload,
+                location: captured.location,
+                backtrace: Some(captured.backtrace),
+                thread: captured.thread,
+                assertion: captured.assertion,
+            },
+            None => Self::uncaptured(payload),
+        }
+    }
+
+    pub(crate) fn uncaptured(payload: Payload) -> Self {
+        Self {
+            payload,
+            location: None,
+            backtrace: None,
+            thread: None,
+            assertion: None,
+        }
+    }
+}
+
+impl From<CaughtPanic> for Payload {
+    #[inline]
+    fn from(panic: CaughtPanic) -> Self {
+        panic.payload
+    }
+}
+
+/// Add the panic hook for capturing panics to the chain. The panic hook can't be changed while
+/// the thread is panicking, e.g. when called from another panic hook, so in that case this does
+/// nothing and a later call adds the hook instead.
+fn install_hook() {
+    static INSTALL_HOOK: Once = Once::new();
+    if thread::panicking() {
+        return;
+    }
+    INSTALL_HOOK.call_once(|| {
+        chain_hook(|info| {
+            if depth(&CAPTURE_DEPTH) != 0 {
+                let captured = Captured {
+                    payload: PayloadSummary::of(info.payload()),
+                    location: info.location().map(PanicLocation::from),
+                    backtrace: Backtrace::capture(),
+                    thread: thread::current().name().map(Box::from),
+                    assertion: ASSERTION.try_with(Cell::get).ok().flatten().map(Box::new),
+                };
+                let _ = CAPTURED.try_with(|slot| {
+                    if let Ok(mut slot) = slot.try_borrow_mut() {
+                        *slot = Some(captured)
+                    }
+                });
+            }
+        })
+        .keep()
+    });
+}
+
+fn take_captured() -> Option<Captured> {
+    let captured = CAPTURED.try_with(|captured| captured.try_borrow_mut().ok()?.take());
+    captured.ok().flatten()
+}
+
+fn depth(key: &'static LocalKey<Cell<usize>>) -> usize {
+    key.try_with(Cell::get).unwrap_or(0)
+}
+
+/// Guard that increases a depth counter on the current thread while it's alive. The counter
+/// isn't changed if the thread-local has been destroyed.
+struct Depth(Option<&'static LocalKey<Cell<usize>>>);
+
+impl Depth {
+    fn enter(key: &'static LocalKey<Cell<usize>>) -> Self {
+        install_hook();
+        let entered = key.try_with(|depth| depth.set(depth.get() + 1)).is_ok();
+        Self(entered.then_some(key))
+    }
+}
+
+impl Drop for Depth {
+    fn drop(&mut self) {
+        if let Some(key) = self.0 {
+            let _ = key.try_with(|depth| depth.set(depth.get() - 1));
+        }
+    }
+}
+
+fn with_depth<R>(key: &'static LocalKey<Cell<usize>>, f: impl FnOnce() -> R) -> R {
+    let _depth = Depth::enter(key);
+    f()
+}
+
+/// Run `f` with the location and backtrace of any panics on the current thread captured for
+/// [`CaughtPanic`]
+#[inline]
+pub(crate) fn with_capture<R>(f: impl FnOnce() -> R) -> R {
+    take_captured();
+    with_depth(&CAPTURE_DEPTH, f)
+}
+
+/// Run `f` without calling the previously installed panic hook for any panics on the current
+/// thread
+#[inline]
+pub(crate) fn with_hook_suppressed<R>(f: impl FnOnce() -> R) -> R {
+    with_depth(&SUPPRESS_DEPTH, f)
+}
+
+/// Enable or disable verbose diagnostics for panics from destructors.
+///
+/// When enabled, the guarded drop functions capture the location of a panic raised by the
+/// destructor they run, and include it in the message they write to stderr when aborting or
+/// logging, so the destructor that panicked can be found. This installs the same panic hook as
+/// [`catch_unwind_captured`], and capturing allocates, so aborting is no longer safe from inside
+/// an allocator or a signal handler while this is enabled. It's disabled by default.
+#[inline]
+pub fn set_verbose_drop_diagnostics(enabled: bool) {
+    VERBOSE_DROPS.store(enabled, Ordering::Relaxed)
+}
+
+/// Check if verbose diagnostics for panics from destructors are enabled. See
+/// [`set_verbose_drop_diagnostics`].
+#[inline]
+pub fn verbose_drop_diagnostics() -> bool {
+    VERBOSE_DROPS.load(Ordering::Relaxed)
+}
+
+/// Run a guarded drop, capturing the location of a panic from it if verbose drop diagnostics
+/// are enabled
+#[inline]
+pub(crate) fn with_drop_capture<R>(f: impl FnOnce() -> R) -> R {
+    if !verbose_drop_diagnostics() {
+        return f();
+    }
+    with_capture(f)
+}
+
+/// Take the location of the panic from the last guarded drop on the current thread, if verbose
+/// drop diagnostics are enabled and it was captured
+#[inline]
+pub(crate) fn take_drop_location() -> Option<PanicLocation> {
+    if !verbose_drop_diagnostics() {
+        return None;
+    }
+    take_captured()?.location
+}
+
+/// Invoke the provided closure and catch any unwinding panics that may occur, capturing the
+/// location and backtrace of the panic. The caught payload is wrapped in [`Payload`], which will
+/// abort if it panics on drop by default.
+///
+/// Returns `Ok` if no panics were caught and `Err(CaughtPanic)` otherwise.
+///
+/// This installs a panic hook the first time it's called, which captures the information and
+/// then calls the previously installed hook.
+///
+/// See [`std::panic::catch_unwind`] for more information.
+#[inline]
+pub fn catch_unwind_captured<F: FnOnce() -> R + UnwindSafe, R>(f: F) -> Result<R, CaughtPanic> {
+    with_capture(|| catch_unwind_wrapped(f)).map_err(CaughtPanic::new)
+}
+
+/// Wrap a closure to assert that it's unwind safe, like [`AssertUnwindSafe`], and record where
+/// the assertion was made.
+///
+/// If a panic from the closure is caught with captured information, e.g. with
+/// [`catch_unwind_captured`], the assertion is available from
+/// [`CaughtPanic::unwind_safety_assertion`] and included in reports, so unwind safety
+/// assertions can be audited when they matter. Use [`assert_unwind_safe_because`] to include a
+/// justification.
+///
+/// ```
+/// use catch_unwind::{assert_unwind_safe, catch_unwind_captured};
+///
+/// let mut count = 0;
+/// let line = line!() + 1;
+/// let panic = catch_unwind_captured(assert_unwind_safe(|| {
+///     count += 1;
+///     panic!("oh no")
+/// }))
+/// .unwrap_err();
+/// assert_eq!(panic.unwind_safety_assertion().unwrap().location().line(), line);
+/// ```
+#[track_caller]
+#[inline]
+pub fn assert_unwind_safe<F: FnOnce() -> R, R>(f: F) -> impl FnOnce() -> R + UnwindSafe {
+    asserted(f, None)
+}
+
+/// Wrap a closure to assert that it's unwind safe, with a justification for why. See
+/// [`assert_unwind_safe`].
+#[track_caller]
+#[inline]
+pub fn assert_unwind_safe_because<F: FnOnce() -> R, R>(
+    justification: &'static str,
+    f: F,
+) -> impl FnOnce() -> R + UnwindSafe {
+    asserted(f, Some(justification))
+}
+
+#[track_caller]
+#[inline]
+fn asserted<F: FnOnce() -> R, R>(
+    f: F,
+    justification: Option<&'static str>,
+) -> impl FnOnce() -> R + UnwindSafe {
+    struct Restore(Option<UnwindSafetyAssertion>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let _ = ASSERTION.try_with(|assertion| assertion.set(self.0));
+        }
+    }
+
+    let assertion = UnwindSafetyAssertion {
+        location: Location::caller(),
+        justification,
+    };
+    let f = AssertUnwindSafe(f);
+    move || {
+        let prev = ASSERTION.try_with(|current| current.replace(Some(assertion)));
+        let _restore = Restore(prev.ok().flatten());
+        f()
+    }
+}
+
+/// Scope in which the location and backtrace of panics on the current thread are captured, for
+/// running many closures with [`CatchScope::catch`] without the setup cost of
+/// [`catch_unwind_captured`] for each one.
+///
+/// ```
+/// use catch_unwind::CatchScope;
+///
+/// let scope = CatchScope::new();
+/// let failures = (0..100)
+///     .filter(|&n| scope.catch(|| assert!(n % 10 != 0)).is_err())
+///     .count();
+/// assert_eq!(failures, 10);
+/// ```
+#[must_use = "panics are only captured while the scope is alive"]
+pub struct CatchScope {
+    _depth: Depth,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl CatchScope {
+    /// Start capturing the location and backtrace of panics on the current thread. This
+    /// installs the same panic hook as [`catch_unwind_captured`] the first time it's called.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            _depth: Depth::enter(&CAPTURE_DEPTH),
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Invoke the provided closure and catch any unwinding panics that may occur, like
+    /// [`catch_unwind_captured`].
+    ///
+    /// Returns `Ok` if no panics were caught and `Err(CaughtPanic)` otherwise.
+    ///
+    /// See [`std::panic::catch_unwind`] for more information.
+    #[inline]
+    pub fn catch<F: FnOnce() -> R + UnwindSafe, R>(&self, f: F) -> Result<R, CaughtPanic> {
+        take_captured();
+        catch_unwind_wrapped(f).map_err(CaughtPanic::new)
+    }
+}
+
+impl Default for CatchScope {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::endless_panic;
+
+    #[test]
+    fn test_catch_unwind_captured() {
+        assert!(matches!(catch_unwind_captured(|| "success"), Ok("success")));
+
+        let line = line!() + 1;
+        let panic = catch_unwind_captured(|| panic!("test")).unwrap_err();
+        assert_eq!(panic.message(), Some("test"));
+        assert_eq!(panic.location().unwrap().file(), file!());
+        assert_eq!(panic.location().unwrap().line(), line);
+        assert!(panic.backtrace().is_some());
+
+        catch_unwind_captured(endless_panic)
+            .unwrap_err()
+            .into_payload()
+            .drop_or_forget();
+    }
+
+    #[test]
+    fn test_capture_not_reused() {
+        let panic = catch_unwind_captured(|| {
+            let _ = catch_unwind_wrapped(|| panic!("inner"));
+            Payload::new(Box::new("foreign")).resume_unwind()
+        })
+        .unwrap_err();
+        assert_eq!(panic.message(), Some("foreign"));
+        assert!(panic.location().is_none());
+        assert!(panic.backtrace().is_none());
+
+        let _ = catch_unwind_captured(|| {
+            let _ = catch_unwind_wrapped(|| panic!("inner"));
+        });
+        let panic = catch_unwind_captured(|| Payload::new(Box::new("inner")).resume_unwind());
+        assert!(panic.unwrap_err().location().is_none());
+    }
+
+    #[test]
+    fn test_catch_scope() {
+        let scope = CatchScope::new();
+        assert!(matches!(scope.catch(|| "success"), Ok("success")));
+        for n in 0..3 {
+            let panic = scope.catch(|| panic!("{n}")).unwrap_err();
+            assert_eq!(panic.message(), Some(n.to_string().as_str()));
+            assert_eq!(panic.location().unwrap().file(), file!());
+        }
+        drop(scope);
+        assert_eq!(depth(&CAPTURE_DEPTH), 0);
+    }
+
+    #[test]
+    fn test_assert_unwind_safe() {
+        let mut value = 0;
+        let line = line!() + 1;
+        let panic = catch_unwind_captured(assert_unwind_safe_because("value is discarded", || {
+            value += 1;
+            panic!("test")
+        }))
+        .unwrap_err();
+        let assertion = panic.unwind_safety_assertion().unwrap();
+        assert_eq!(assertion.location().line(), line);
+        assert_eq!(assertion.justification(), Some("value is discarded"));
+        assert!(assertion.to_string().ends_with(": value is discarded"));
+
+        let panic = catch_unwind_captured(|| panic!("test")).unwrap_err();
+        assert!(panic.unwind_safety_assertion().is_none());
+        assert_eq!(
+            catch_unwind_captured(assert_unwind_safe(|| value)).ok(),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_verbose_drop_diagnostics() {
+        // the setting is process-wide, so it's only changed in a child process
+        if !crate::tests::in_child() {
+            let output =
+                crate::tests::run_in_child("capture::tests::test_verbose_drop_diagnostics");
+            crate::tests::assert_passed(&output);
+            return;
+        }
+
+        struct OnDrop<F: FnMut()>(F);
+
+        impl<F: FnMut()> Drop for OnDrop<F> {
+            fn drop(&mut self) {
+                (self.0)()
+            }
+        }
+
+        assert!(!verbose_drop_diagnostics());
+        set_verbose_drop_diagnostics(true);
+        let line = line!() + 1;
+        let payload = crate::drop_wrapped(OnDrop(|| panic!("boom"))).unwrap_err();
+        let location = take_drop_location().unwrap();
+        payload.drop_or_forget();
+        assert_eq!(location.file(), file!());
+        assert_eq!(location.line(), line);
+    }
+
+    #[test]
+    fn test_reentrant_while_panicking() {
+        struct InDrop;
+
+        impl Drop for InDrop {
+            fn drop(&mut self) {
+                assert!(thread::panicking());
+                assert!(matches!(catch_unwind_captured(|| "success"), Ok("success")));
+                let _scope = CatchScope::new();
+                crate::drop_or_abort(String::new());
+            }
+        }
+
+        let result = std::panic::catch_unwind(|| {
+            let _in_drop = InDrop;
+            panic!("test")
+        });
+        assert!(result.is_err());
+    }
+}
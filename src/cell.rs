@@ -0,0 +1,126 @@
+//! Surviving borrow violations and panics in `RefCell`-heavy code
+
+use crate::{catch_unwind_wrapped, Payload};
+use core::{
+    cell::{BorrowMutError, RefCell},
+    error::Error,
+    fmt,
+    panic::AssertUnwindSafe,
+};
+
+/// Error from [`try_with_borrow_mut`]
+pub enum BorrowOrPanic {
+    /// The cell was already borrowed
+    Borrow(BorrowMutError),
+
+    /// The closure panicked, e.g. because it borrowed the cell again. The caught payload is
+    /// wrapped in [`Payload`], which will abort if it panics on drop by default.
+    Panic(Payload),
+}
+
+impl BorrowOrPanic {
+    /// Get the payload, if the closure panicked
+    #[inline]
+    pub fn into_payload(self) -> Option<Payload> {
+        match self {
+            Self::Borrow(_) => None,
+            Self::Panic(payload) => Some(payload),
+        }
+    }
+}
+
+impl fmt::Debug for BorrowOrPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Borrow(err) => f.debug_tuple("Borrow").field(err).finish(),
+            Self::Panic(payload) => f
+                .debug_tuple("Panic")
+                .field(&payload.message().unwrap_or("Box<dyn Any>"))
+                .finish(),
+        }
+    }
+}
+
+impl fmt::Display for BorrowOrPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Borrow(err) => write!(f, "{err}"),
+            Self::Panic(payload) => match payload.message() {
+                Some(msg) => write!(f, "panicked: {msg}"),
+                None => write!(f, "panicked"),
+            },
+        }
+    }
+}
+
+impl Error for BorrowOrPanic {}
+
+/// Mutably borrow the value in a cell and invoke the provided closure with it, and catch any
+/// unwinding panics that may occur. If the cell is already borrowed, the closure isn't called.
+///
+/// A re-entrant borrow of the cell from inside the closure panics, and that panic is caught
+/// like any other. The borrow is released while unwinding, so the cell can be borrowed again
+/// afterwards. The value is passed to the closure by mutable reference, so it's left in
+/// whatever state the closure's last completed operation put it in.
+///
+/// The closure doesn't have to be [`UnwindSafe`](core::panic::UnwindSafe), so that it can
+/// capture a reference to the cell itself or to other cells, which is what re-entrant callbacks
+/// do. Any shared state it captures can be observed after a panic.
+///
+/// Returns `Ok` if the borrow succeeded and no panics were caught, and `Err(BorrowOrPanic)`
+/// otherwise.
+///
+/// ```
+/// use catch_unwind::cell::{try_with_borrow_mut, BorrowOrPanic};
+/// use std::cell::RefCell;
+///
+/// let cell = RefCell::new(0);
+/// let result = try_with_borrow_mut(&cell, |value| {
+///     *value += 1;
+///     *cell.borrow_mut() += 1;
+/// });
+/// assert!(matches!(result, Err(BorrowOrPanic::Panic(_))));
+/// assert_eq!(*cell.borrow(), 1);
+/// ```
+///
+/// See [`std::panic::catch_unwind`] for more information.
+#[inline]
+pub fn try_with_borrow_mut<T: ?Sized, F: FnOnce(&mut T) -> R, R>(
+    cell: &RefCell<T>,
+    f: F,
+) -> Result<R, BorrowOrPanic> {
+    let mut value = cell.try_borrow_mut().map_err(BorrowOrPanic::Borrow)?;
+    // the value and the closure's captures can be observed after a panic, as documented
+    catch_unwind_wrapped(AssertUnwindSafe(|| f(&mut value))).map_err(BorrowOrPanic::Panic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::endless_panic;
+
+    #[test]
+    fn test_try_with_borrow_mut() {
+        let cell = RefCell::new(String::from("a"));
+        let result = try_with_borrow_mut(&cell, |value| {
+            value.push('b');
+            value.len()
+        });
+        assert_eq!(result.ok(), Some(2));
+
+        let borrow = cell.borrow();
+        let result = try_with_borrow_mut(&cell, |_| unreachable!());
+        assert!(matches!(result, Err(BorrowOrPanic::Borrow(_))));
+        drop(borrow);
+
+        let result = try_with_borrow_mut(&cell, |_| drop(cell.borrow()));
+        assert!(result.unwrap_err().to_string().starts_with("panicked"));
+
+        try_with_borrow_mut(&cell, |_| endless_panic())
+            .unwrap_err()
+            .into_payload()
+            .unwrap()
+            .drop_or_forget();
+        assert_eq!(*cell.borrow(), "ab");
+    }
+}
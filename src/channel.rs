@@ -0,0 +1,285 @@
+//! Forwarding caught panics between threads
+
+use crate::{catch_unwind_wrapped, thread::default_policy, DropPolicy, Payload};
+use std::{
+    fmt,
+    panic::UnwindSafe,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+};
+
+/// Payload on its way through a channel, dropped according to the channel's policy if it's
+/// never received
+struct InTransit {
+    payload: Option<Payload>,
+    policy: DropPolicy,
+}
+
+impl InTransit {
+    #[inline]
+    fn into_payload(mut self) -> Payload {
+        self.payload.take().unwrap()
+    }
+}
+
+impl Drop for InTransit {
+    #[inline]
+    fn drop(&mut self) {
+        if let Some(payload) = self.payload.take() {
+            payload.drop_with_policy(self.policy)
+        }
+    }
+}
+
+/// Create a channel for forwarding caught panic payloads from one thread to another, e.g. from
+/// a worker to a coordinator that can resume the panic on its own thread.
+///
+/// Payloads that are dropped in transit, because the receiver is gone or the channel is dropped
+/// with payloads still buffered, are dropped according to the current thread's default policy.
+///
+/// ```
+/// use catch_unwind::channel::payload_channel;
+/// use std::thread;
+///
+/// let (sender, receiver) = payload_channel();
+/// thread::spawn(move || sender.catch(|| panic!("oh no")))
+///     .join()
+///     .unwrap();
+/// let payload = receiver.try_recv().unwrap();
+/// assert_eq!(payload.message(), Some("oh no"));
+/// ```
+#[inline]
+pub fn payload_channel() -> (PayloadSender, PayloadReceiver) {
+    payload_channel_with_policy(default_policy())
+}
+
+/// Create a channel for forwarding caught panic payloads, where payloads that are dropped in
+/// transit are dropped according to `policy`. See [`payload_channel`].
+pub fn payload_channel_with_policy(policy: DropPolicy) -> (PayloadSender, PayloadReceiver) {
+    let (sender, receiver) = mpsc::channel();
+    (
+        PayloadSender { sender, policy },
+        PayloadReceiver { receiver },
+    )
+}
+
+/// Sending half of a channel created with [`payload_channel`]
+#[derive(Clone)]
+pub struct PayloadSender {
+    sender: Sender<InTransit>,
+    policy: DropPolicy,
+}
+
+impl fmt::Debug for PayloadSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PayloadSender")
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PayloadSender {
+    /// Send a payload to the receiver.
+    ///
+    /// Returns `true` if the payload was sent, and `false` if the receiver is gone, in which
+    /// case the payload is dropped according to the channel's policy.
+    #[inline]
+    pub fn send(&self, payload: Payload) -> bool {
+        let payload = InTransit {
+            payload: Some(payload),
+            policy: self.policy,
+        };
+        self.sender.send(payload).is_ok()
+    }
+
+    /// Invoke the provided closure and catch any unwinding panics that may occur, sending the
+    /// caught payload to the receiver.
+    ///
+    /// Returns `Some` if no panics were caught and `None` otherwise.
+    ///
+    /// See [`std::panic::catch_unwind`] for more information.
+    #[inline]
+    pub fn catch<F: FnOnce() -> R + UnwindSafe, R>(&self, f: F) -> Option<R> {
+        catch_unwind_wrapped(f)
+            .map_err(|payload| self.send(payload))
+            .ok()
+    }
+}
+
+/// Receiving half of a channel created with [`payload_channel`]
+pub struct PayloadReceiver {
+    receiver: Receiver<InTransit>,
+}
+
+impl fmt::Debug for PayloadReceiver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PayloadReceiver").finish_non_exhaustive()
+    }
+}
+
+impl PayloadReceiver {
+    /// Wait for a payload to be sent.
+    ///
+    /// Returns `None` if every sender is gone and no payloads are left.
+    #[inline]
+    pub fn recv(&self) -> Option<Payload> {
+        self.receiver.recv().ok().map(InTransit::into_payload)
+    }
+
+    /// Get a payload that has been sent, if there is one, without waiting
+    #[inline]
+    pub fn try_recv(&self) -> Option<Payload> {
+        self.receiver.try_recv().ok().map(InTransit::into_payload)
+    }
+
+    /// Resume unwinding on the current thread with a payload that has been sent, if there is
+    /// one, without waiting. Otherwise, return normally.
+    #[inline]
+    pub fn try_resume(&self) {
+        if let Some(payload) = self.try_recv() {
+            payload.resume_unwind()
+        }
+    }
+}
+
+/// Handle for propagating a panic caught by another component back to the thread that owns
+/// the handle. Clones of the handle share the same slot.
+///
+/// The component catches panics with [`catch`](Self::catch) or stores them with
+/// [`store`](Self::store), and the owner later calls [`check`](Self::check) to resume unwinding
+/// with the caught payload on its own thread. Only the first payload is kept, and later ones are
+/// dropped according to the default policy of the thread that stores them.
+///
+/// ```
+/// use catch_unwind::{catch_unwind_wrapped, channel::PropagateHandle};
+/// use std::thread;
+///
+/// let handle = PropagateHandle::new();
+/// let worker = handle.clone();
+/// thread::spawn(move || worker.catch(|| panic!("oh no")))
+///     .join()
+///     .unwrap();
+/// let payload = catch_unwind_wrapped(|| handle.check()).unwrap_err();
+/// assert_eq!(payload.message(), Some("oh no"));
+/// ```
+#[derive(Clone, Default)]
+pub struct PropagateHandle {
+    slot: Arc<Mutex<Option<Payload>>>,
+}
+
+impl fmt::Debug for PropagateHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PropagateHandle")
+            .field("panicked", &self.panicked())
+            .finish()
+    }
+}
+
+impl PropagateHandle {
+    /// Create a new handle with no payload stored
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store a payload to be propagated, unless one has already been stored. Otherwise, the
+    /// payload is dropped according to the current thread's default policy.
+    pub fn store(&self, payload: Payload) {
+        let mut slot = self.slot.lock().unwrap_or_else(|err| err.into_inner());
+        let rejected = match *slot {
+            None => slot.replace(payload),
+            Some(_) => Some(payload),
+        };
+        // the rejected payload is dropped after unlocking, in case it panics
+        drop(slot);
+        drop(rejected)
+    }
+
+    /// Invoke the provided closure and catch any unwinding panics that may occur, storing the
+    /// caught payload to be propagated.
+    ///
+    /// Returns `Some` if no panics were caught and `None` otherwise.
+    ///
+    /// See [`std::panic::catch_unwind`] for more information.
+    #[inline]
+    pub fn catch<F: FnOnce() -> R + UnwindSafe, R>(&self, f: F) -> Option<R> {
+        catch_unwind_wrapped(f)
+            .map_err(|payload| self.store(payload))
+            .ok()
+    }
+
+    /// Check if a payload has been stored
+    #[inline]
+    pub fn panicked(&self) -> bool {
+        self.slot
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .is_some()
+    }
+
+    /// Take the stored payload, if any, without propagating it
+    #[inline]
+    pub fn take(&self) -> Option<Payload> {
+        self.slot
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take()
+    }
+
+    /// Resume unwinding on the current thread with the stored payload, if any. Otherwise,
+    /// return normally.
+    #[inline]
+    pub fn check(&self) {
+        if let Some(payload) = self.take() {
+            payload.resume_unwind()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::endless_panic;
+    use std::thread;
+
+    #[test]
+    fn test_payload_channel() {
+        let (sender, receiver) = payload_channel_with_policy(DropPolicy::Forget);
+        assert_eq!(sender.catch(|| "success"), Some("success"));
+        receiver.try_resume();
+
+        let worker = sender.clone();
+        thread::spawn(move || worker.catch(|| panic!("test")))
+            .join()
+            .unwrap();
+        let result = catch_unwind_wrapped(|| receiver.try_resume());
+        assert_eq!(result.unwrap_err().message(), Some("test"));
+
+        assert_eq!(sender.catch(endless_panic), None);
+        drop(receiver);
+        assert_eq!(sender.catch(endless_panic), None);
+    }
+
+    #[test]
+    fn test_propagate_handle() {
+        let handle = PropagateHandle::new();
+        assert_eq!(handle.catch(|| "success"), Some("success"));
+        assert!(!handle.panicked());
+        handle.check();
+
+        let worker = handle.clone();
+        thread::spawn(move || {
+            worker.catch(|| panic!("first"));
+            crate::thread::set_default_policy(DropPolicy::Forget);
+            worker.catch(endless_panic);
+        })
+        .join()
+        .unwrap();
+        assert!(handle.panicked());
+        let result = catch_unwind_wrapped(|| handle.check());
+        assert_eq!(result.unwrap_err().message(), Some("first"));
+        assert!(handle.take().is_none());
+    }
+}
@@ -0,0 +1,124 @@
+//! Running cleanup closures in reverse order under panic protection
+
+use crate::{catch_unwind_wrapped, MultiPayload};
+use alloc::{boxed::Box, vec::Vec};
+use core::{fmt, mem, panic::AssertUnwindSafe};
+
+/// Stack of cleanup closures that are run in reverse order of being pushed, either explicitly
+/// with [`run`](Self::run) or when the stack is dropped.
+///
+/// Each closure is run under [`catch_unwind_wrapped`], so a panicking cleanup doesn't skip the
+/// rest. When the stack is dropped, the caught payloads are dropped according to the current
+/// thread's default policy.
+///
+/// ```
+/// use catch_unwind::cleanup::CleanupStack;
+/// use std::cell::RefCell;
+///
+/// let log = RefCell::new(Vec::new());
+/// let mut cleanup = CleanupStack::new();
+/// cleanup.push(|| log.borrow_mut().push("first"));
+/// cleanup.push(|| panic!("oh no"));
+/// cleanup.push(|| log.borrow_mut().push("last"));
+/// let panics = cleanup.run();
+/// assert_eq!(*log.borrow(), ["last", "first"]);
+/// assert_eq!(panics.len(), 1);
+/// ```
+#[derive(Default)]
+pub struct CleanupStack<'a> {
+    cleanups: Vec<Box<dyn FnOnce() + 'a>>,
+}
+
+impl<'a> CleanupStack<'a> {
+    /// Create an empty cleanup stack
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            cleanups: Vec::new(),
+        }
+    }
+
+    /// Push a cleanup closure onto the stack
+    #[inline]
+    pub fn push(&mut self, cleanup: impl FnOnce() + 'a) {
+        self.cleanups.push(Box::new(cleanup))
+    }
+
+    /// Get the number of cleanup closures on the stack
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.cleanups.len()
+    }
+
+    /// Check if the stack is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.cleanups.is_empty()
+    }
+
+    /// Remove all cleanup closures from the stack without running them
+    #[inline]
+    pub fn dismiss(&mut self) {
+        self.cleanups.clear()
+    }
+
+    /// Run the cleanup closures in reverse order, and catch any unwinding panics that may occur
+    /// in them.
+    ///
+    /// Returns the caught payloads, in the order they were caught.
+    pub fn run(mut self) -> MultiPayload {
+        self.run_all()
+    }
+
+    fn run_all(&mut self) -> MultiPayload {
+        let mut panics = MultiPayload::new();
+        for cleanup in mem::take(&mut self.cleanups).into_iter().rev() {
+            // the cleanup closure is consumed, so it can't be observed after a panic
+            if let Err(payload) = catch_unwind_wrapped(AssertUnwindSafe(cleanup)) {
+                panics.push(payload);
+            }
+        }
+        panics
+    }
+}
+
+impl Drop for CleanupStack<'_> {
+    fn drop(&mut self) {
+        drop(self.run_all())
+    }
+}
+
+impl fmt::Debug for CleanupStack<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CleanupStack")
+            .field("len", &self.cleanups.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::RefCell;
+
+    #[test]
+    fn test_cleanup_stack() {
+        let log = RefCell::new(Vec::new());
+        {
+            let mut cleanup = CleanupStack::new();
+            cleanup.push(|| log.borrow_mut().push(1));
+            cleanup.push(|| panic!("test"));
+            cleanup.push(|| log.borrow_mut().push(2));
+        }
+        assert_eq!(*log.borrow(), [2, 1]);
+
+        let mut cleanup = CleanupStack::new();
+        cleanup.push(crate::tests::endless_panic);
+        cleanup.push(|| log.borrow_mut().push(3));
+        cleanup.push(crate::tests::endless_panic);
+        let panics = cleanup.run();
+        assert_eq!(panics.len(), 2);
+        panics.drop_or_forget();
+        assert_eq!(*log.borrow(), [2, 1, 3]);
+    }
+}
@@ -0,0 +1,204 @@
+//! Panic-safe operations on collections
+
+use crate::{catch_unwind_wrapped, drop_wrapped, MultiPayload, Payload};
+use alloc::{collections::VecDeque, vec::Vec};
+use core::{
+    cmp::Ordering,
+    iter,
+    panic::{AssertUnwindSafe, UnwindSafe},
+};
+
+/// Remove and drop every element of a vector, and catch any unwinding panics that may occur
+/// while dropping them. Dropping continues past elements that panic, so no element is leaked.
+///
+/// Returns `Ok` if no panics were caught, and `Err` with the caught payloads otherwise, in the
+/// order of the elements that panicked.
+///
+/// ```
+/// use catch_unwind::collections::drain_caught;
+///
+/// struct Bomb;
+///
+/// impl Drop for Bomb {
+///     fn drop(&mut self) {
+///         panic!("boom");
+///     }
+/// }
+///
+/// let mut values = vec![Some(Bomb), None, Some(Bomb)];
+/// let panics = drain_caught(&mut values).unwrap_err();
+/// assert!(values.is_empty());
+/// assert_eq!(panics.len(), 2);
+/// ```
+pub fn drain_caught<T>(vec: &mut Vec<T>) -> Result<(), MultiPayload> {
+    drop_all(vec.drain(..))
+}
+
+/// Remove and drop every element of a deque, and catch any unwinding panics that may occur
+/// while dropping them. See [`drain_caught`].
+pub fn drain_deque_caught<T>(deque: &mut VecDeque<T>) -> Result<(), MultiPayload> {
+    drop_all(deque.drain(..))
+}
+
+fn drop_all<T>(values: impl Iterator<Item = T>) -> Result<(), MultiPayload> {
+    let panics: MultiPayload = values
+        .filter_map(|value| drop_wrapped(value).err())
+        .collect();
+    if panics.is_empty() {
+        Ok(())
+    } else {
+        Err(panics)
+    }
+}
+
+/// Sort a slice with a comparator function, like [`slice::sort_by`], and catch any unwinding
+/// panics that may occur in the comparator.
+///
+/// If the comparator panics, sorting stops and the slice is left with all its elements in an
+/// unspecified order.
+///
+/// Returns `Ok` if no panics were caught and `Err(Payload)` otherwise.
+///
+/// ```
+/// use catch_unwind::collections::sort_by_caught;
+///
+/// let mut values = [3, 1, 2];
+/// assert!(sort_by_caught(&mut values, |a, b| a.cmp(b)).is_ok());
+/// assert_eq!(values, [1, 2, 3]);
+///
+/// let mut values = [3.0, f64::NAN, 1.0];
+/// let payload = sort_by_caught(&mut values, |a, b| a.partial_cmp(b).unwrap()).unwrap_err();
+/// payload.drop_or_forget();
+/// ```
+///
+/// See [`std::panic::catch_unwind`] for more information.
+pub fn sort_by_caught<T, F: FnMut(&T, &T) -> Ordering + UnwindSafe>(
+    slice: &mut [T],
+    compare: F,
+) -> Result<(), Payload> {
+    // sorting leaves the slice as a permutation of its elements if the comparator panics
+    catch_unwind_wrapped(AssertUnwindSafe(|| slice.sort_by(compare)))
+}
+
+/// Retain only the elements of a vector for which the predicate returns `true`, like
+/// [`Vec::retain`], and catch any unwinding panics that may occur in the predicate or while
+/// dropping the removed elements.
+///
+/// An element for which the predicate panics is kept. After a panic, the predicate isn't called
+/// again and all the remaining elements are kept, so the pass always completes. The removed
+/// elements are dropped after the vector has been compacted, and dropping continues past
+/// elements that panic, like [`drain_caught`].
+///
+/// Returns `Ok` if no panics were caught, and `Err` with the caught payloads otherwise. A
+/// payload from the predicate comes first, followed by the payloads from dropping the removed
+/// elements in their original order.
+///
+/// ```
+/// use catch_unwind::collections::retain_caught;
+///
+/// let mut values = vec![1, 5, 0, 3, 4];
+/// let panics = retain_caught(&mut values, |n| 10 / n > 2).unwrap_err();
+/// assert_eq!(values, [1, 0, 3, 4]);
+/// panics.drop_or_forget();
+/// ```
+///
+/// See [`std::panic::catch_unwind`] for more information.
+pub fn retain_caught<T, F: FnMut(&T) -> bool + UnwindSafe>(
+    vec: &mut Vec<T>,
+    mut predicate: F,
+) -> Result<(), MultiPayload> {
+    let mut caught = None;
+    let removed: Vec<T> = vec
+        .extract_if(.., |value| {
+            if caught.is_some() {
+                return false;
+            }
+            // `predicate` is unwind safe, and the element is kept if it panics
+            match catch_unwind_wrapped(AssertUnwindSafe(|| predicate(value))) {
+                Ok(keep) => !keep,
+                Err(payload) => {
+                    caught = Some(payload);
+                    false
+                }
+            }
+        })
+        .collect();
+    match (caught, drop_all(removed.into_iter())) {
+        (None, result) => result,
+        (Some(payload), Ok(())) => Err(iter::once(payload).collect()),
+        (Some(payload), Err(panics)) => Err(iter::once(payload).chain(panics).collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{endless_panic, Bomb};
+
+    #[test]
+    fn test_drain_caught() {
+        let mut values = vec![1, 2];
+        assert!(drain_caught(&mut values).is_ok());
+        assert!(values.is_empty());
+
+        let mut values = vec![Some(Bomb), None, Some(Bomb)];
+        let panics = drain_caught(&mut values).unwrap_err();
+        assert!(values.is_empty());
+        assert_eq!(panics.len(), 2);
+        panics.drop_or_forget();
+
+        let mut values: VecDeque<_> = [None, Some(Bomb)].into_iter().collect();
+        let panics = drain_deque_caught(&mut values).unwrap_err();
+        assert!(values.is_empty());
+        assert_eq!(panics.len(), 1);
+        panics.drop_or_forget();
+    }
+
+    #[test]
+    fn test_sort_by_caught() {
+        let mut values = [5, 3, 1, 4, 2];
+        let payload = sort_by_caught(&mut values, |a, b| {
+            if *a == 1 || *b == 1 {
+                endless_panic()
+            }
+            a.cmp(b)
+        })
+        .unwrap_err();
+        payload.drop_or_forget();
+        values.sort();
+        assert_eq!(values, [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_retain_caught() {
+        let mut values = vec![1, 2, 3, 4];
+        assert!(retain_caught(&mut values, |n| n % 2 == 0).is_ok());
+        assert_eq!(values, [2, 4]);
+
+        let mut values = vec![1, 2, 3, 4, 5];
+        let panics = retain_caught(&mut values, |&n| {
+            if n == 3 {
+                endless_panic()
+            }
+            n % 2 == 0
+        })
+        .unwrap_err();
+        assert_eq!(panics.len(), 1);
+        panics.drop_or_forget();
+        assert_eq!(values, [2, 3, 4, 5]);
+
+        let mut values = vec![(1, Some(Bomb)), (2, None), (3, Some(Bomb)), (4, None)];
+        let panics = retain_caught(&mut values, |(n, _)| {
+            if *n == 4 {
+                panic!("test")
+            }
+            n % 2 == 0
+        })
+        .unwrap_err();
+        assert_eq!(panics.len(), 3);
+        assert_eq!(panics.iter().next().unwrap().message(), Some("test"));
+        panics.drop_or_forget();
+        let values: Vec<_> = values.iter().map(|(n, _)| *n).collect();
+        assert_eq!(values, [2, 4]);
+    }
+}
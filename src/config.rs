@@ -0,0 +1,176 @@
+//! Catching panics with several options configured together
+
+use crate::{
+    capture::{with_capture, with_hook_suppressed},
+    catch_unwind_wrapped, handle_caught,
+    report::report_caught_guarded,
+    thread::default_policy,
+    CatchPolicy, CaughtPanic, DropPolicy, PanicReporter,
+};
+use std::{
+    panic::{AssertUnwindSafe, UnwindSafe},
+    sync::Arc,
+};
+
+/// Configuration for [`catch_unwind_with_config`]
+#[derive(Clone)]
+pub struct CatchConfig {
+    policy: Option<DropPolicy>,
+    catch_policy: CatchPolicy,
+    capture: bool,
+    reporter: Option<Arc<dyn PanicReporter>>,
+    retries: u32,
+    suppress_hook: bool,
+}
+
+impl Default for CatchConfig {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CatchConfig {
+    /// Create a new configuration. By default caught panics are handled with
+    /// [`CatchPolicy::Ignore`] and the current thread's default drop policy is used, the
+    /// location and backtrace of caught panics are captured, caught panics aren't reported or
+    /// retried, and the panic hook is called as usual.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            policy: None,
+            catch_policy: CatchPolicy::Ignore,
+            capture: true,
+            reporter: None,
+            retries: 0,
+            suppress_hook: false,
+        }
+    }
+
+    /// Set the policy for handling a panic payload that panics when dropped
+    #[inline]
+    pub fn policy(mut self, policy: DropPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Set the policy for handling a caught panic, before its payload is dropped. This is
+    /// applied to every caught panic, including those that are retried.
+    #[inline]
+    pub fn catch_policy(mut self, catch_policy: CatchPolicy) -> Self {
+        self.catch_policy = catch_policy;
+        self
+    }
+
+    /// Set whether the location and backtrace of caught panics are captured
+    #[inline]
+    pub fn capture(mut self, capture: bool) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    /// Set the reporter that caught panics are sent to
+    #[inline]
+    pub fn reporter(mut self, reporter: impl PanicReporter + 'static) -> Self {
+        self.reporter = Some(Arc::new(reporter));
+        self
+    }
+
+    /// Set how many times the closure is invoked again after it panics
+    #[inline]
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Set whether the panic hook that was installed before this crate's own is skipped for
+    /// panics in the closure, e.g. to keep expected panics from being printed
+    #[inline]
+    pub fn suppress_hook(mut self, suppress_hook: bool) -> Self {
+        self.suppress_hook = suppress_hook;
+        self
+    }
+
+    fn catch<F: FnOnce() -> R + UnwindSafe, R>(&self, f: F) -> Result<R, CaughtPanic> {
+        let catch = || {
+            if self.capture {
+                with_capture(|| catch_unwind_wrapped(f)).map_err(CaughtPanic::new)
+            } else {
+                catch_unwind_wrapped(f).map_err(CaughtPanic::uncaptured)
+            }
+        };
+        if self.suppress_hook {
+            with_hook_suppressed(catch)
+        } else {
+            catch()
+        }
+    }
+}
+
+/// Invoke the provided closure and catch any unwinding panics that may occur, as configured by
+/// `config`.
+///
+/// Each caught panic is reported to the configured reporter, if any. If the configuration allows
+/// more retries, the payload is dropped according to the configured policy and the closure is
+/// invoked again. Otherwise the last caught panic is returned instead of being dropped.
+///
+/// Returns `Ok` if an invocation completed without panicking and `Err(CaughtPanic)` otherwise.
+///
+/// See [`std::panic::catch_unwind`] for more information.
+pub fn catch_unwind_with_config<F: FnMut() -> R + UnwindSafe, R>(
+    config: &CatchConfig,
+    mut f: F,
+) -> Result<R, CaughtPanic> {
+    let policy = config.policy.unwrap_or_else(default_policy);
+    let mut retries = 0;
+    loop {
+        match config.catch(AssertUnwindSafe(&mut f)) {
+            Ok(ok) => return Ok(ok),
+            Err(panic) => {
+                if let Some(reporter) = &config.reporter {
+                    report_caught_guarded(&**reporter, &panic, policy);
+                }
+                handle_caught(panic.payload().get(), config.catch_policy);
+                if retries == config.retries {
+                    return Err(panic);
+                }
+                panic.into_payload().drop_with_policy(policy);
+                retries += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_catch_unwind_with_config() {
+        static REPORTED: AtomicUsize = AtomicUsize::new(0);
+
+        let config = CatchConfig::new()
+            .retries(2)
+            .suppress_hook(true)
+            .reporter(|_: &_| {
+                REPORTED.fetch_add(1, Ordering::Relaxed);
+            });
+        let attempts = AtomicUsize::new(0);
+        let result = catch_unwind_with_config(&config, || {
+            let attempt = attempts.fetch_add(1, Ordering::Relaxed) + 1;
+            if attempt < 3 {
+                panic!("attempt {attempt}")
+            }
+            attempt
+        });
+        assert!(matches!(result, Ok(3)));
+        assert_eq!(REPORTED.load(Ordering::Relaxed), 2);
+
+        let panic =
+            catch_unwind_with_config(&config.capture(false), || panic!("test")).unwrap_err();
+        assert_eq!(panic.message(), Some("test"));
+        assert!(panic.location().is_none());
+        assert_eq!(REPORTED.load(Ordering::Relaxed), 5);
+    }
+}
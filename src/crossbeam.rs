@@ -0,0 +1,138 @@
+//! Guarded wrapper for [`crossbeam`](https://docs.rs/crossbeam)'s scoped threads
+//!
+//! Crossbeam collects panics from scoped threads as raw payloads, and may drop them while tearing
+//! down the scope. This wrapper catches the panics in each thread instead, so every payload is
+//! protected by [`Payload`].
+
+use crate::{
+    catch_unwind_wrapped,
+    thread::{inherit_policy, inherited_policy},
+    MultiPayload, Payload,
+};
+use crossbeam_utils::thread;
+use std::{
+    iter, mem,
+    panic::AssertUnwindSafe,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+type Slot = Arc<Mutex<Option<Payload>>>;
+
+/// Scope for spawning guarded threads. See [`scope`].
+pub struct Scope<'a, 'env> {
+    inner: &'a thread::Scope<'env>,
+    slots: Arc<Mutex<Vec<Slot>>>,
+}
+
+impl<'env> Scope<'_, 'env> {
+    /// Spawn a scoped thread, like [`crossbeam_utils::thread::Scope::spawn`]. If the thread
+    /// panics, the payload is returned from [`ScopedJoinHandle::join`], or from [`scope`] if the
+    /// thread wasn't joined.
+    ///
+    /// The spawned thread inherits the spawning thread's default policy. See
+    /// [`set_default_policy`](crate::thread::set_default_policy).
+    pub fn spawn<'scope, F, T>(&'scope self, f: F) -> ScopedJoinHandle<'scope, T>
+    where
+        F: FnOnce(&Scope<'_, 'env>) -> T + Send + 'env,
+        T: Send + 'env,
+    {
+        let slot = Slot::default();
+        lock(&self.slots).push(Arc::clone(&slot));
+        let thread_slot = Arc::clone(&slot);
+        let slots = Arc::clone(&self.slots);
+        let policy = inherited_policy();
+        let inner = self.inner.spawn(move |inner| {
+            inherit_policy(policy);
+            let scope = Scope { inner, slots };
+            match catch_unwind_wrapped(AssertUnwindSafe(|| f(&scope))) {
+                Ok(ok) => Some(ok),
+                Err(payload) => {
+                    *lock(&thread_slot) = Some(payload);
+                    None
+                }
+            }
+        });
+        ScopedJoinHandle { inner, slot }
+    }
+}
+
+/// Handle for joining a guarded scoped thread
+pub struct ScopedJoinHandle<'scope, T> {
+    inner: thread::ScopedJoinHandle<'scope, Option<T>>,
+    slot: Slot,
+}
+
+impl<T> ScopedJoinHandle<'_, T> {
+    /// Wait for the thread to finish. Returns `Ok` if the thread didn't panic, and `Err` with the
+    /// caught payload otherwise.
+    pub fn join(self) -> Result<T, Payload> {
+        match self.inner.join() {
+            Ok(Some(ok)) => Ok(ok),
+            Ok(None) => Err(lock(&self.slot).take().unwrap()),
+            Err(err) => Err(Payload::new(err)),
+        }
+    }
+}
+
+/// Create a scope for spawning threads, like [`crossbeam_utils::thread::scope`], and catch any
+/// unwinding panics that occur in `f` or in any of the spawned threads that weren't joined.
+///
+/// Returns `Ok` if no panics were caught, and `Err` with all the caught payloads otherwise.
+pub fn scope<'env, F, R>(f: F) -> Result<R, MultiPayload>
+where
+    F: FnOnce(&Scope<'_, 'env>) -> R,
+{
+    let slots = Arc::new(Mutex::new(Vec::new()));
+    let result = thread::scope(|inner| {
+        catch_unwind_wrapped(AssertUnwindSafe(|| {
+            f(&Scope {
+                inner,
+                slots: Arc::clone(&slots),
+            })
+        }))
+    });
+    let panics: MultiPayload = mem::take(&mut *lock(&slots))
+        .iter()
+        .filter_map(|slot| lock(slot).take())
+        .collect();
+    match result {
+        Ok(Ok(ok)) if panics.is_empty() => Ok(ok),
+        Ok(Ok(_)) => Err(panics),
+        Ok(Err(payload)) => Err(iter::once(payload).chain(panics).collect()),
+        Err(err) => Err(iter::once(Payload::new(err)).chain(panics).collect()),
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::endless_panic;
+
+    #[test]
+    fn test_scope() {
+        assert!(matches!(scope(|_| "success"), Ok("success")));
+
+        let panics = scope(|s| {
+            s.spawn(|_| endless_panic());
+            s.spawn(|_| endless_panic())
+                .join()
+                .unwrap_err()
+                .drop_or_forget();
+            assert_eq!(s.spawn(|_| "success").join().ok(), Some("success"));
+        })
+        .unwrap_err();
+        assert_eq!(panics.len(), 1);
+        panics.drop_or_forget();
+
+        let panics = scope(|s| {
+            s.spawn(|_| panic!("thread"));
+            panic!("body")
+        })
+        .unwrap_err();
+        assert_eq!(panics.messages().collect::<Vec<_>>(), ["body", "thread"]);
+    }
+}
@@ -0,0 +1,47 @@
+//! Converting caught panics into [`eyre::Report`](::eyre::Report)
+
+use crate::{panic_message, thread::default_policy, CaughtPanic, Payload};
+use ::eyre::Report;
+
+/// Convert a caught panic into a report with the panic message. The payload is dropped according
+/// to the current thread's default policy.
+impl From<Payload> for Report {
+    fn from(payload: Payload) -> Self {
+        let report = Report::msg(panic_message!(payload).to_owned());
+        payload.drop_with_policy(default_policy());
+        report
+    }
+}
+
+/// Convert a caught panic into a report with the panic message, with the location of the panic
+/// attached as context if it was captured. The payload is dropped according to the current
+/// thread's default policy.
+impl From<CaughtPanic> for Report {
+    fn from(panic: CaughtPanic) -> Self {
+        let mut report = Report::msg(panic_message!(panic).to_owned());
+        if let Some(location) = panic.location() {
+            report = report.wrap_err(format!("panicked at {location}"));
+        }
+        panic.into_payload().drop_with_policy(default_policy());
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catch_unwind_captured;
+
+    #[test]
+    fn test_into_report() {
+        let panic = catch_unwind_captured(|| panic!("test")).unwrap_err();
+        let report = Report::from(panic);
+        assert!(report
+            .to_string()
+            .starts_with(concat!("panicked at ", file!())));
+        assert_eq!(report.root_cause().to_string(), "test");
+
+        let payload = crate::catch_unwind_wrapped(|| panic!("test")).unwrap_err();
+        assert_eq!(Report::from(payload).to_string(), "test");
+    }
+}
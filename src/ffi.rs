@@ -0,0 +1,548 @@
+//! Panic boundaries for FFI callbacks
+
+use crate::{
+    catch_unwind_or_abort, catch_unwind_wrapped, drop_with_policy, thread::default_policy, Payload,
+};
+use std::{
+    any::{Any, TypeId},
+    cell::RefCell,
+    ffi::{c_void, CString},
+    panic::{resume_unwind, AssertUnwindSafe, UnwindSafe},
+    ptr,
+    sync::{PoisonError, RwLock},
+};
+
+thread_local! {
+    static LAST_PANIC: RefCell<Option<Payload>> = const { RefCell::new(None) };
+}
+
+/// Return type of an FFI function with a value that signals an error to the caller
+pub trait FfiReturn {
+    /// Value returned to signal an error, such as a caught panic
+    fn error_value() -> Self;
+}
+
+impl FfiReturn for () {
+    #[inline]
+    fn error_value() -> Self {}
+}
+
+impl FfiReturn for bool {
+    /// Returns `false`
+    #[inline]
+    fn error_value() -> Self {
+        false
+    }
+}
+
+impl<T> FfiReturn for *const T {
+    /// Returns a null pointer
+    #[inline]
+    fn error_value() -> Self {
+        ptr::null()
+    }
+}
+
+impl<T> FfiReturn for *mut T {
+    /// Returns a null pointer
+    #[inline]
+    fn error_value() -> Self {
+        ptr::null_mut()
+    }
+}
+
+impl<T> FfiReturn for Option<T> {
+    /// Returns `None`. This is useful for nullable function pointers and references, and for
+    /// `NonNull`
+    #[inline]
+    fn error_value() -> Self {
+        None
+    }
+}
+
+macro_rules! impl_ffi_return {
+    ($value:expr => $($ty:ty),*) => { $(
+        impl FfiReturn for $ty {
+            #[doc = concat!("Returns `", stringify!($value), "`")]
+            #[inline]
+            fn error_value() -> Self {
+                $value
+            }
+        }
+    )* };
+}
+
+impl_ffi_return!(-1 => i8, i16, i32, i64, isize);
+impl_ffi_return!(Self::MAX => u8, u16, u32, u64, usize);
+
+/// Invoke the provided closure and catch any unwinding panics that may occur, for use as the
+/// body of an `extern "C"` function.
+///
+/// Returns the closure's result if no panics were caught, and [`FfiReturn::error_value`]
+/// otherwise. The payload is dropped according to the current thread's default policy.
+///
+/// ```
+/// use catch_unwind::ffi::ffi_boundary;
+///
+/// extern "C" fn parse(value: u32) -> i32 {
+///     ffi_boundary(|| i32::try_from(value).unwrap())
+/// }
+/// # assert_eq!(parse(u32::MAX), -1);
+/// ```
+#[inline]
+pub fn ffi_boundary<F: FnOnce() -> R + UnwindSafe, R: FfiReturn>(f: F) -> R {
+    match catch_unwind_wrapped(f) {
+        Ok(ok) => ok,
+        Err(payload) => {
+            payload.drop_with_policy(default_policy());
+            R::error_value()
+        }
+    }
+}
+
+enum StatusMatcher {
+    Type(TypeId),
+    Message(String),
+}
+
+static STATUS_CODES: RwLock<Vec<(StatusMatcher, i32)>> = RwLock::new(Vec::new());
+
+/// Register a status code for panics with a payload of type `T`. See [`ffi_boundary_coded`].
+pub fn register_status_code<T: Any>(code: i32) {
+    STATUS_CODES
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push((StatusMatcher::Type(TypeId::of::<T>()), code));
+}
+
+/// Register a status code for panics with a message that contains `pattern`. See
+/// [`ffi_boundary_coded`].
+pub fn register_status_code_for_message(pattern: impl Into<String>, code: i32) {
+    STATUS_CODES
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push((StatusMatcher::Message(pattern.into()), code));
+}
+
+/// Get the status code registered for a payload. The first matching registration is used, and
+/// if there's none, the result is `-1`.
+pub fn status_code_for(payload: &Payload) -> i32 {
+    let type_id = payload.get().type_id();
+    let message = payload.message();
+    STATUS_CODES
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .find(|(matcher, _)| match matcher {
+            StatusMatcher::Type(id) => *id == type_id,
+            StatusMatcher::Message(pattern) => message.is_some_and(|msg| msg.contains(pattern)),
+        })
+        .map_or(-1, |(_, code)| *code)
+}
+
+/// Invoke the provided closure and catch any unwinding panics that may occur, for use as the
+/// body of an `extern "C"` function that returns a status code.
+///
+/// Returns the closure's result if no panics were caught, and the status code registered for
+/// the payload otherwise (see [`status_code_for`]). The payload is dropped according to the
+/// current thread's default policy.
+#[inline]
+pub fn ffi_boundary_coded<F: FnOnce() -> i32 + UnwindSafe>(f: F) -> i32 {
+    match catch_unwind_wrapped(f) {
+        Ok(ok) => ok,
+        Err(payload) => {
+            let code = status_code_for(&payload);
+            payload.drop_with_policy(default_policy());
+            code
+        }
+    }
+}
+
+/// Invoke the provided closure and catch any unwinding panics that may occur, for use as the
+/// body of an `extern "C"` function. This is like [`ffi_boundary`], but the payload is stored
+/// with [`store_last_panic`] instead of being dropped.
+#[inline]
+pub fn ffi_boundary_recorded<F: FnOnce() -> R + UnwindSafe, R: FfiReturn>(f: F) -> R {
+    ffi_boundary_recorded_or(R::error_value(), f)
+}
+
+/// Invoke the provided closure and catch any unwinding panics that may occur, for use as the
+/// body of an `extern "C"` function. This is like [`ffi_boundary_recorded`], but `error_value`
+/// is returned if a panic was caught.
+#[inline]
+pub fn ffi_boundary_recorded_or<F: FnOnce() -> R + UnwindSafe, R>(error_value: R, f: F) -> R {
+    match catch_unwind_wrapped(f) {
+        Ok(ok) => ok,
+        Err(payload) => {
+            store_last_panic(payload);
+            error_value
+        }
+    }
+}
+
+/// Declare an exported `extern "C"` function whose body is wrapped in a guarded catch.
+///
+/// If the body panics, the payload is stored with [`store_last_panic`](crate::ffi::store_last_panic)
+/// and the error value of the return type is returned (see
+/// [`FfiReturn`](crate::ffi::FfiReturn)). A different value can be returned instead by adding
+/// `or <value>` after the body, like with
+/// [`ffi_boundary_recorded_or`](crate::ffi::ffi_boundary_recorded_or). The body is assumed to be
+/// unwind safe.
+///
+/// ```
+/// use catch_unwind::{export_guarded, ffi::take_last_panic};
+/// use std::ffi::{c_char, CStr};
+///
+/// export_guarded! {
+///     /// Get the length of a C string
+///     pub unsafe fn example_strlen(s: *const c_char) -> usize {
+///         unsafe { CStr::from_ptr(s) }.count_bytes()
+///     }
+/// }
+///
+/// export_guarded! {
+///     fn example_half(value: i32) -> i32 {
+///         assert!(value % 2 == 0, "value is odd");
+///         value / 2
+///     } or -2
+/// }
+///
+/// assert_eq!(unsafe { example_strlen(c"abc".as_ptr()) }, 3);
+/// assert_eq!(example_half(3), -2);
+/// assert_eq!(take_last_panic().unwrap().message(), Some("value is odd"));
+/// ```
+#[macro_export]
+macro_rules! export_guarded {
+    // the parameters are passed through as tokens, because a `pat` fragment can't be followed
+    // by `:`
+    (
+        @fn [$($unsafe:ident)?] [$(#[$attr:meta])*] [$vis:vis]
+        $name:ident($($params:tt)*) $(-> $ret:ty)? $body:block
+    ) => {
+        $(#[$attr])*
+        #[unsafe(no_mangle)]
+        $vis $($unsafe)? extern "C" fn $name($($params)*) $(-> $ret)? {
+            $crate::ffi::ffi_boundary_recorded(::core::panic::AssertUnwindSafe(move || $body))
+        }
+    };
+
+    (
+        @fn [$($unsafe:ident)?] [$(#[$attr:meta])*] [$vis:vis]
+        $name:ident($($params:tt)*) -> $ret:ty $body:block or $err:expr
+    ) => {
+        $(#[$attr])*
+        #[unsafe(no_mangle)]
+        $vis $($unsafe)? extern "C" fn $name($($params)*) -> $ret {
+            $crate::ffi::ffi_boundary_recorded_or(
+                $err,
+                ::core::panic::AssertUnwindSafe(move || $body),
+            )
+        }
+    };
+
+    ($(#[$attr:meta])* $vis:vis unsafe fn $($rest:tt)*) => {
+        $crate::export_guarded!(@fn [unsafe] [$(#[$attr])*] [$vis] $($rest)*);
+    };
+
+    ($(#[$attr:meta])* $vis:vis fn $($rest:tt)*) => {
+        $crate::export_guarded!(@fn [] [$(#[$attr])*] [$vis] $($rest)*);
+    };
+}
+
+/// Store a payload as the last caught panic on the current thread, so it can be retrieved later
+/// with [`take_last_panic`]. A previously stored payload is dropped.
+///
+/// This allows the panic to be inspected by a follow-up call from foreign code that only
+/// received an error value, like `errno`.
+#[inline]
+pub fn store_last_panic(payload: Payload) {
+    let _prev = LAST_PANIC.try_with(|last| last.replace(Some(payload)));
+}
+
+/// Take the last caught panic stored on the current thread. See [`store_last_panic`].
+#[inline]
+pub fn take_last_panic() -> Option<Payload> {
+    LAST_PANIC.try_with(RefCell::take).ok().flatten()
+}
+
+/// Get the message of the last caught panic stored on the current thread as a C string,
+/// without taking it. See [`Payload::to_c_string`] and [`store_last_panic`].
+#[inline]
+pub fn last_panic_message_c() -> Option<CString> {
+    LAST_PANIC
+        .try_with(|last| last.borrow().as_ref().map(Payload::to_c_string))
+        .ok()
+        .flatten()
+}
+
+impl Payload {
+    /// Get the panic message as a C string, for passing to foreign code.
+    ///
+    /// NUL characters in the message are replaced with U+FFFD, and if the payload isn't a
+    /// string, the message is `Box<dyn Any>` like in the standard library's panic hook.
+    pub fn to_c_string(&self) -> CString {
+        let msg = crate::panic_message!(self);
+        CString::new(msg.replace('\0', "\u{fffd}")).unwrap()
+    }
+}
+
+/// Invoke the provided closure in an `extern "C-unwind"` function, deliberately letting panics
+/// unwind into the foreign caller.
+///
+/// If the closure panics, unwinding is resumed with the payload wrapped in [`Payload`], so if
+/// foreign code catches and discards the panic, the payload is still dropped according to the
+/// dropping thread's default policy. Use [`catch_c_unwind`] on the receiving side to unwrap it
+/// again.
+#[inline]
+pub fn unwind_outward<F: FnOnce() -> R + UnwindSafe, R>(f: F) -> R {
+    match catch_unwind_wrapped(f) {
+        Ok(ok) => ok,
+        Err(payload) => resume_unwind(Box::new(payload)),
+    }
+}
+
+/// Invoke the provided closure and catch any Rust panics that may unwind out of it, for calling
+/// `extern "C-unwind"` functions. A payload that was wrapped by [`unwind_outward`] is unwrapped.
+///
+/// Foreign exceptions, such as C++ exceptions, can't be caught by Rust and abort the process
+/// if they unwind into this function.
+///
+/// Returns `Ok` if no panics were caught and `Err(Payload)` otherwise.
+#[inline]
+pub fn catch_c_unwind<F: FnOnce() -> R + UnwindSafe, R>(f: F) -> Result<R, Payload> {
+    catch_unwind_wrapped(f).map_err(|payload| match payload.into_inner().downcast::<Payload>() {
+        Ok(payload) => *payload,
+        Err(payload) => Payload(Some(payload)),
+    })
+}
+
+/// Rust closure owned for use as a C callback. See [`wrap_callback`].
+pub struct Callback<F> {
+    data: *mut F,
+}
+
+impl<F> Callback<F> {
+    /// Get the user data pointer to pass to the trampoline. It's valid for as long as this
+    /// `Callback` exists.
+    #[inline]
+    pub fn user_data(&self) -> *mut c_void {
+        self.data.cast()
+    }
+
+    /// Get the trampoline function to pass to foreign code together with the user data
+    /// pointer. The type of the trampoline is usually inferred from where it's used.
+    #[inline]
+    pub fn trampoline<T: Trampoline<F>>(&self) -> T {
+        T::trampoline()
+    }
+}
+
+impl<F> Drop for Callback<F> {
+    fn drop(&mut self) {
+        // SAFETY: `data` was created with `Box::into_raw` in `wrap_callback`
+        drop_with_policy(unsafe { Box::from_raw(self.data) }, default_policy())
+    }
+}
+
+/// `extern "C"` function pointer type that can call a closure of type `F` through a user data
+/// pointer. This is implemented for `unsafe extern "C" fn(*mut c_void, A1, A2, ...) -> R` with
+/// up to six arguments after the user data pointer, where `F: FnMut(A1, A2, ...) -> R` and
+/// `R: FfiReturn`.
+///
+/// # Safety
+/// The function returned by `trampoline` must only be called with a user data pointer from a
+/// [`Callback<F>`] that still exists, and not concurrently with itself.
+pub unsafe trait Trampoline<F>: Copy {
+    /// Get the trampoline function
+    fn trampoline() -> Self;
+}
+
+macro_rules! impl_trampoline {
+    ($($arg:ident: $ty:ident),*) => {
+        // SAFETY: the trampoline only accesses the closure through the user data pointer
+        unsafe impl<F: FnMut($($ty),*) -> R, $($ty,)* R: FfiReturn> Trampoline<F>
+            for unsafe extern "C" fn(*mut c_void $(, $ty)*) -> R
+        {
+            #[inline]
+            fn trampoline() -> Self {
+                unsafe extern "C" fn trampoline<F: FnMut($($ty),*) -> R, $($ty,)* R: FfiReturn>(
+                    data: *mut c_void $(, $arg: $ty)*
+                ) -> R {
+                    // SAFETY: `data` comes from a `Callback<F>` that still exists
+                    let f = unsafe { &mut *data.cast::<F>() };
+                    catch_unwind_or_abort(AssertUnwindSafe(|| f($($arg),*)))
+                        .unwrap_or_else(R::error_value)
+                }
+                trampoline::<F, $($ty,)* R>
+            }
+        }
+    };
+}
+
+impl_trampoline!();
+impl_trampoline!(a1: A1);
+impl_trampoline!(a1: A1, a2: A2);
+impl_trampoline!(a1: A1, a2: A2, a3: A3);
+impl_trampoline!(a1: A1, a2: A2, a3: A3, a4: A4);
+impl_trampoline!(a1: A1, a2: A2, a3: A3, a4: A4, a5: A5);
+impl_trampoline!(a1: A1, a2: A2, a3: A3, a4: A4, a5: A5, a6: A6);
+
+/// Wrap a Rust closure for use as a C callback that takes a user data pointer as its first
+/// argument. The closure is invoked under [`catch_unwind_or_abort`], and returns
+/// [`FfiReturn::error_value`] if it panics.
+///
+/// ```
+/// use catch_unwind::ffi::wrap_callback;
+/// use std::ffi::c_void;
+///
+/// type Callback = unsafe extern "C" fn(*mut c_void, i32) -> i32;
+///
+/// // Foreign function that takes a callback
+/// unsafe extern "C" fn call(callback: Callback, user_data: *mut c_void) -> i32 {
+///     unsafe { callback(user_data, 2) }
+/// }
+///
+/// let mut sum = 0;
+/// let callback = wrap_callback(|x: i32| {
+///     sum += x;
+///     sum
+/// });
+/// assert_eq!(unsafe { call(callback.trampoline(), callback.user_data()) }, 2);
+/// ```
+#[inline]
+pub fn wrap_callback<F>(f: F) -> Callback<F> {
+    Callback {
+        data: Box::into_raw(Box::new(f)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::endless_panic, DropPolicy};
+
+    #[test]
+    fn test_ffi_boundary() {
+        assert_eq!(ffi_boundary(|| 1_i32), 1);
+        assert_eq!(ffi_boundary(|| -> u8 { panic!() }), u8::MAX);
+        assert!(ffi_boundary(|| -> *const u8 { panic!() }).is_null());
+
+        crate::thread::set_default_policy(DropPolicy::Forget);
+        ffi_boundary(endless_panic);
+    }
+
+    #[test]
+    fn test_ffi_boundary_coded() {
+        struct OutOfMemory;
+
+        register_status_code::<OutOfMemory>(-2);
+        register_status_code_for_message("invalid argument", -3);
+        assert_eq!(ffi_boundary_coded(|| 0), 0);
+        assert_eq!(
+            ffi_boundary_coded(|| std::panic::panic_any(OutOfMemory)),
+            -2
+        );
+        assert_eq!(ffi_boundary_coded(|| panic!("invalid argument: x")), -3);
+        assert_eq!(ffi_boundary_coded(|| panic!("other")), -1);
+    }
+
+    #[test]
+    fn test_last_panic() {
+        assert!(take_last_panic().is_none());
+        assert_eq!(ffi_boundary_recorded(|| -> i32 { panic!("test") }), -1);
+        assert_eq!(take_last_panic().unwrap().message(), Some("test"));
+        assert!(take_last_panic().is_none());
+    }
+
+    #[test]
+    fn test_to_c_string() {
+        let payload = crate::catch_unwind_wrapped(|| panic!("a\0b")).unwrap_err();
+        assert_eq!(payload.to_c_string().to_str(), Ok("a\u{fffd}b"));
+
+        let payload = crate::catch_unwind_wrapped(|| std::panic::panic_any(1)).unwrap_err();
+        assert_eq!(payload.to_c_string().to_str(), Ok("Box<dyn Any>"));
+
+        store_last_panic(payload);
+        assert_eq!(last_panic_message_c().unwrap().to_str(), Ok("Box<dyn Any>"));
+        assert!(take_last_panic().is_some());
+    }
+
+    #[test]
+    fn test_wrap_callback() {
+        let callback = wrap_callback(|a: u32, b: u32| a.checked_sub(b).unwrap());
+        let trampoline: unsafe extern "C" fn(*mut c_void, u32, u32) -> u32 = callback.trampoline();
+        assert_eq!(unsafe { trampoline(callback.user_data(), 3, 2) }, 1);
+        assert_eq!(unsafe { trampoline(callback.user_data(), 2, 3) }, u32::MAX);
+    }
+
+    #[test]
+    fn test_c_unwind() {
+        extern "C-unwind" fn outward(value: i32) -> i32 {
+            unwind_outward(|| {
+                assert!(value >= 0, "negative");
+                value
+            })
+        }
+
+        assert!(matches!(catch_c_unwind(|| outward(1)), Ok(1)));
+        let payload = catch_c_unwind(|| outward(-1)).unwrap_err();
+        assert_eq!(payload.message(), Some("negative"));
+    }
+
+    #[test]
+    fn test_export_guarded() {
+        #[repr(C)]
+        struct Pair {
+            a: i32,
+            b: i32,
+        }
+
+        crate::export_guarded! {
+            fn catch_unwind_test_pair_div(Pair { a, b }: Pair, _: u8) -> i32 {
+                a / b
+            } or -2
+        }
+
+        crate::export_guarded! {
+            fn catch_unwind_test_fail(_unused: i32) -> *const u8 {
+                panic!("test")
+            }
+        }
+
+        assert_eq!(catch_unwind_test_pair_div(Pair { a: 6, b: 3 }, 0), 2);
+        assert_eq!(catch_unwind_test_pair_div(Pair { a: 6, b: 0 }, 0), -2);
+        assert!(take_last_panic().is_some());
+        assert!(catch_unwind_test_fail(0).is_null());
+        assert_eq!(take_last_panic().unwrap().message(), Some("test"));
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn test_ffi_catch_unwind() {
+        #[crate::ffi_catch_unwind]
+        extern "C" fn checked_div(a: i32, b: i32) -> i32 {
+            if a < 0 {
+                return 0;
+            }
+            a / b
+        }
+
+        #[crate::ffi_catch_unwind(record)]
+        extern "C" fn fail() -> *const u8 {
+            panic!("test")
+        }
+
+        #[crate::ffi_catch_unwind(record, crate = "crate")]
+        extern "system" fn fail_system() -> i32 {
+            panic!("system")
+        }
+
+        assert_eq!(checked_div(6, 3), 2);
+        assert_eq!(checked_div(-6, 3), 0);
+        assert_eq!(checked_div(6, 0), -1);
+        assert!(fail().is_null());
+        assert_eq!(take_last_panic().unwrap().message(), Some("test"));
+        assert_eq!(fail_system(), -1);
+        assert_eq!(take_last_panic().unwrap().message(), Some("system"));
+    }
+}
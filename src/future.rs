@@ -0,0 +1,367 @@
+//! Catching panics in futures
+
+use crate::{abort::abort, catch_unwind_wrapped, rt, thread::default_policy, DropPolicy, Payload};
+use std::{
+    future::Future,
+    panic::{AssertUnwindSafe, UnwindSafe},
+    pin::Pin,
+    task::{Context, Poll},
+    thread,
+};
+
+/// Future that catches unwinding panics from polling the inner future. See
+/// [`catch_unwind_future`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct CatchUnwind<F> {
+    inner: F,
+}
+
+impl<F> CatchUnwind<F> {
+    #[inline]
+    fn project(self: Pin<&mut Self>) -> Pin<&mut F> {
+        // SAFETY: `inner` is structurally pinned
+        unsafe { self.map_unchecked_mut(|this| &mut this.inner) }
+    }
+}
+
+impl<F: Future + UnwindSafe> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, Payload>;
+
+    #[inline]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = self.project();
+        poll_catch_unwind(cx, |cx| inner.poll(cx))
+    }
+}
+
+/// Future that catches unwinding panics from polling the inner future, and drops the payload
+/// according to a [`DropPolicy`]. See [`FutureExt::catch_unwind_with_policy`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct CatchUnwindWithPolicy<F> {
+    inner: CatchUnwind<F>,
+    policy: DropPolicy,
+}
+
+impl<F> CatchUnwindWithPolicy<F> {
+    #[inline]
+    fn project(self: Pin<&mut Self>) -> (Pin<&mut CatchUnwind<F>>, DropPolicy) {
+        // SAFETY: `inner` is structurally pinned, and `policy` is `Copy`
+        unsafe {
+            let this = self.get_unchecked_mut();
+            (Pin::new_unchecked(&mut this.inner), this.policy)
+        }
+    }
+}
+
+impl<F: Future + UnwindSafe> Future for CatchUnwindWithPolicy<F> {
+    type Output = Option<F::Output>;
+
+    #[inline]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let (inner, policy) = self.project();
+        inner.poll(cx).map(|result| match result {
+            Ok(ok) => Some(ok),
+            Err(payload) => {
+                payload.drop_with_policy(policy);
+                None
+            }
+        })
+    }
+}
+
+/// Future that runs a cleanup closure if the inner future panics or is dropped before it
+/// completes. See [`async_defer`].
+#[must_use = "futures do nothing unless polled"]
+pub struct AsyncDefer<F, C: FnOnce()> {
+    inner: F,
+    cleanup: Option<C>,
+    policy: DropPolicy,
+}
+
+impl<F, C: FnOnce()> AsyncDefer<F, C> {
+    /// Set the policy for handling a panic in the cleanup closure while another panic is already
+    /// in progress. [`DropPolicy::Abort`] aborts the process, and the other policies discard the
+    /// panic from the cleanup closure.
+    #[inline]
+    pub fn policy(mut self, policy: DropPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    #[inline]
+    fn project(self: Pin<&mut Self>) -> (Pin<&mut F>, &mut Option<C>) {
+        // SAFETY: `inner` is structurally pinned, and `cleanup` isn't
+        unsafe {
+            let this = self.get_unchecked_mut();
+            (Pin::new_unchecked(&mut this.inner), &mut this.cleanup)
+        }
+    }
+}
+
+impl<F: Future, C: FnOnce()> Future for AsyncDefer<F, C> {
+    type Output = F::Output;
+
+    #[inline]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // if the inner future panics, the cleanup is run when this is dropped during unwinding
+        let (inner, cleanup) = self.project();
+        let poll = inner.poll(cx);
+        if poll.is_ready() {
+            *cleanup = None;
+        }
+        poll
+    }
+}
+
+impl<F, C: FnOnce()> Drop for AsyncDefer<F, C> {
+    fn drop(&mut self) {
+        if let Some(cleanup) = self.cleanup.take() {
+            if thread::panicking() {
+                if let Err(secondary) = catch_unwind_wrapped(AssertUnwindSafe(cleanup)) {
+                    cleanup_panicked(secondary, self.policy);
+                }
+            } else {
+                cleanup()
+            }
+        }
+    }
+}
+
+fn cleanup_panicked(payload: Payload, policy: DropPolicy) {
+    if policy != DropPolicy::Forget {
+        match payload.message() {
+            Some(msg) => rt::log(format_args!(
+                "catch-unwind: cleanup panicked during another panic: {msg}"
+            )),
+            None => rt::log(format_args!(
+                "catch-unwind: cleanup panicked during another panic"
+            )),
+        }
+    }
+    match policy {
+        DropPolicy::Abort => abort(),
+        DropPolicy::Forget | DropPolicy::Log => payload.drop_with_policy(policy),
+    }
+}
+
+/// Wrap a future so that `cleanup` is run if the future panics or is dropped before it
+/// completes, e.g. when it's cancelled. The cleanup isn't run if the future completes normally.
+///
+/// If the cleanup closure panics while another panic is in progress, the panic is handled
+/// according to the policy set with [`AsyncDefer::policy`], which defaults to the current
+/// thread's default policy. Otherwise a panic from the cleanup closure unwinds normally.
+#[inline]
+pub fn async_defer<F: Future, C: FnOnce()>(fut: F, cleanup: C) -> AsyncDefer<F, C> {
+    AsyncDefer {
+        inner: fut,
+        cleanup: Some(cleanup),
+        policy: default_policy(),
+    }
+}
+
+/// Extension trait for catching panics in futures
+pub trait FutureExt: Future + Sized {
+    /// Assert that this future is unwind safe, so it can be used with the catch combinators.
+    /// See [`assert_unwind_safe_future`].
+    #[inline]
+    fn assert_unwind_safe(self) -> AssertUnwindSafe<Self> {
+        AssertUnwindSafe(self)
+    }
+
+    /// Catch any unwinding panics that may occur while polling this future. If the panic
+    /// payload panics when dropped, abort the process.
+    ///
+    /// The future resolves to `Some` if no panics were caught and `None` otherwise.
+    #[inline]
+    fn catch_unwind_or_abort(self) -> CatchUnwindWithPolicy<Self>
+    where
+        Self: UnwindSafe,
+    {
+        self.catch_unwind_with_policy(DropPolicy::Abort)
+    }
+
+    /// Catch any unwinding panics that may occur while polling this future. If the panic
+    /// payload panics when dropped, `mem::forget` the new panic payload.
+    ///
+    /// The future resolves to `Some` if no panics were caught and `None` otherwise.
+    #[inline]
+    fn catch_unwind_or_forget(self) -> CatchUnwindWithPolicy<Self>
+    where
+        Self: UnwindSafe,
+    {
+        self.catch_unwind_with_policy(DropPolicy::Forget)
+    }
+
+    /// Catch any unwinding panics that may occur while polling this future. If the panic
+    /// payload panics when dropped, handle it according to `policy`.
+    ///
+    /// The future resolves to `Some` if no panics were caught and `None` otherwise.
+    #[inline]
+    fn catch_unwind_with_policy(self, policy: DropPolicy) -> CatchUnwindWithPolicy<Self>
+    where
+        Self: UnwindSafe,
+    {
+        CatchUnwindWithPolicy {
+            inner: catch_unwind_future(self),
+            policy,
+        }
+    }
+
+    /// Catch any unwinding panics that may occur while polling this future. See
+    /// [`catch_unwind_future`].
+    #[inline]
+    fn catch_unwind_wrapped(self) -> CatchUnwind<Self>
+    where
+        Self: UnwindSafe,
+    {
+        catch_unwind_future(self)
+    }
+}
+
+impl<F: Future> FutureExt for F {}
+
+/// Invoke the provided poll function and catch any unwinding panics that may occur. This is
+/// for guarding a single poll call in a manual implementation of `Future` or `Stream`:
+///
+/// ```
+/// # use catch_unwind::{future::poll_catch_unwind, Payload};
+/// # use std::{future::Future, pin::Pin, task::{Context, Poll}};
+/// struct Guarded<F>(Pin<Box<F>>);
+///
+/// impl<F: Future> Future for Guarded<F> {
+///     type Output = Result<F::Output, Payload>;
+///
+///     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+///         poll_catch_unwind(cx, |cx| self.0.as_mut().poll(cx))
+///     }
+/// }
+/// ```
+///
+/// Returns `Ready(Ok)` or `Pending` if no panics were caught and `Ready(Err(Payload))`
+/// otherwise. The poll function is assumed to be unwind safe, as the thing being polled
+/// shouldn't be polled again after a panic.
+#[inline]
+pub fn poll_catch_unwind<T, F: FnOnce(&mut Context<'_>) -> Poll<T>>(
+    cx: &mut Context<'_>,
+    f: F,
+) -> Poll<Result<T, Payload>> {
+    match catch_unwind_wrapped(AssertUnwindSafe(|| f(cx))) {
+        Ok(poll) => poll.map(Ok),
+        Err(payload) => Poll::Ready(Err(payload)),
+    }
+}
+
+/// Assert that a future is unwind safe. This is the same as wrapping it in [`AssertUnwindSafe`],
+/// which implements `Future`.
+///
+/// This is useful for futures that capture `&mut` references or other state that isn't
+/// [`UnwindSafe`], when it's known that a caught panic can't leave that state broken.
+#[inline]
+pub fn assert_unwind_safe_future<F: Future>(fut: F) -> AssertUnwindSafe<F> {
+    AssertUnwindSafe(fut)
+}
+
+/// Wrap a future to catch any unwinding panics that may occur while polling it. The caught
+/// payload is wrapped in [`Payload`], which will abort if it panics on drop by default.
+///
+/// The future resolves to `Ok` if no panics were caught and `Err(Payload)` otherwise. It
+/// shouldn't be polled again after it has resolved.
+///
+/// See [`std::panic::catch_unwind`] for more information.
+#[inline]
+pub fn catch_unwind_future<F: Future + UnwindSafe>(fut: F) -> CatchUnwind<F> {
+    CatchUnwind { inner: fut }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::tests::endless_panic;
+    use std::{pin::pin, task::Waker};
+
+    pub(crate) fn block_on<F: Future>(fut: F) -> F::Output {
+        let mut fut = pin!(fut);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn test_catch_unwind_future() {
+        assert!(matches!(
+            block_on(catch_unwind_future(async { "success" })),
+            Ok("success")
+        ));
+        block_on(catch_unwind_future(async { endless_panic() }))
+            .unwrap_err()
+            .drop_or_forget();
+    }
+
+    #[test]
+    fn test_poll_catch_unwind() {
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(matches!(
+            poll_catch_unwind(&mut cx, |_| Poll::Ready("success")),
+            Poll::Ready(Ok("success"))
+        ));
+        match poll_catch_unwind(&mut cx, |_| {
+            endless_panic();
+            Poll::Ready(())
+        }) {
+            Poll::Ready(Err(payload)) => payload.drop_or_forget(),
+            _ => panic!("panic wasn't caught"),
+        }
+    }
+
+    #[test]
+    fn test_future_ext() {
+        assert_eq!(
+            block_on(async { "success" }.catch_unwind_or_abort()),
+            Some("success")
+        );
+        assert_eq!(
+            block_on(async { endless_panic() }.catch_unwind_or_forget()),
+            None
+        );
+        block_on(async { endless_panic() }.catch_unwind_wrapped())
+            .unwrap_err()
+            .drop_or_forget();
+    }
+
+    #[test]
+    fn test_async_defer() {
+        let mut cleaned = false;
+        block_on(async_defer(async {}, || cleaned = true));
+        assert!(!cleaned);
+
+        drop(async_defer(async {}, || cleaned = true));
+        assert!(cleaned);
+
+        let mut cleaned = false;
+        let fut = async_defer(async { endless_panic() }, || {
+            cleaned = true;
+            panic!("cleanup")
+        })
+        .policy(DropPolicy::Forget);
+        catch_unwind_wrapped(AssertUnwindSafe(|| block_on(fut)))
+            .unwrap_err()
+            .drop_or_forget();
+        assert!(cleaned);
+    }
+
+    #[test]
+    fn test_assert_unwind_safe() {
+        let mut value = 0;
+        let fut = async { value += 1 };
+        assert_eq!(
+            block_on(fut.assert_unwind_safe().catch_unwind_or_abort()),
+            Some(())
+        );
+        assert_eq!(value, 1);
+    }
+}
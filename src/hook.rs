@@ -0,0 +1,158 @@
+//! Composing panic hooks
+
+use std::{
+    cell::Cell,
+    fmt,
+    panic::{self, PanicHookInfo},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, Once,
+    },
+    thread,
+};
+
+type Link = Arc<dyn Fn(&PanicHookInfo) + Send + Sync>;
+
+static LINKS: Mutex<Vec<(u64, Link)>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// While nonzero, the hook that was installed before the chain isn't called for panics on
+    /// this thread, e.g. to keep expected panics in tests quiet
+    pub(crate) static SUPPRESS_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Install the panic hook that calls the chained hooks, wrapping the hook that was installed
+/// before. The panic hook can't be changed while the thread is panicking, so in that case this
+/// does nothing and a later call installs the hook instead.
+fn install_hook() {
+    static INSTALL_HOOK: Once = Once::new();
+    if thread::panicking() {
+        return;
+    }
+    INSTALL_HOOK.call_once(|| {
+        let prev = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            // the links are called without holding the lock, so they can chain or detach hooks
+            let links: Vec<Link> = {
+                let links = LINKS.lock().unwrap_or_else(|err| err.into_inner());
+                links.iter().rev().map(|(_, link)| link.clone()).collect()
+            };
+            for link in links {
+                link(info)
+            }
+            if SUPPRESS_DEPTH.try_with(Cell::get).unwrap_or(0) == 0 {
+                prev(info)
+            }
+        }));
+    });
+}
+
+/// Add a panic hook that's called before the currently installed hooks, which are still called
+/// afterwards, like chaining with [`std::panic::take_hook`] and [`std::panic::set_hook`].
+///
+/// Hooks added with this function are called with the most recently added one first, followed
+/// by the hook that was installed before the first one was added. Unlike chaining manually, a
+/// hook can be removed again without disturbing the others, by dropping the returned
+/// [`HookLink`]. Use [`HookLink::keep`] to keep the hook for the rest of the process. The hooks
+/// this crate uses for capturing panics and guarding thread-local destructors are links in the
+/// same chain.
+///
+/// The hook that calls the chained hooks is installed the first time this is called. The panic
+/// hook can't be changed while the thread is panicking, so if this is first called from a panic
+/// hook or a destructor during unwinding, the added hook isn't called until this is called
+/// again when the thread isn't panicking.
+///
+/// ```
+/// use catch_unwind::chain_hook;
+/// use std::{
+///     panic::catch_unwind,
+///     sync::atomic::{AtomicUsize, Ordering},
+/// };
+///
+/// static PANICS: AtomicUsize = AtomicUsize::new(0);
+///
+/// let link = chain_hook(|_| {
+///     PANICS.fetch_add(1, Ordering::Relaxed);
+/// });
+/// catch_unwind(|| panic!("oh no")).unwrap_err();
+/// drop(link);
+/// catch_unwind(|| panic!("oh no")).unwrap_err();
+/// assert_eq!(PANICS.load(Ordering::Relaxed), 1);
+/// ```
+pub fn chain_hook(hook: impl Fn(&PanicHookInfo) + Send + Sync + 'static) -> HookLink {
+    install_hook();
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    LINKS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .push((id, Arc::new(hook)));
+    HookLink { id }
+}
+
+/// Hook added with [`chain_hook`]. The hook is removed from the chain when this is dropped,
+/// which can be done from any thread.
+#[must_use = "the hook is removed when the link is dropped"]
+pub struct HookLink {
+    id: u64,
+}
+
+impl HookLink {
+    /// Keep the hook in the chain for the rest of the process
+    #[inline]
+    pub fn keep(self) {
+        std::mem::forget(self)
+    }
+}
+
+impl fmt::Debug for HookLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HookLink").finish_non_exhaustive()
+    }
+}
+
+impl Drop for HookLink {
+    fn drop(&mut self) {
+        let removed = {
+            let mut links = LINKS.lock().unwrap_or_else(|err| err.into_inner());
+            let index = links.iter().position(|(id, _)| *id == self.id);
+            index.map(|index| links.remove(index))
+        };
+        // the hook is dropped after unlocking, in case it panics
+        drop(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "abort-only"))]
+    use crate::catch_unwind_wrapped;
+
+    #[test]
+    #[cfg(not(feature = "abort-only"))]
+    fn test_chain_hook() {
+        thread_local! {
+            static CALLS: std::cell::RefCell<Vec<u32>> = const { std::cell::RefCell::new(Vec::new()) };
+        }
+
+        let record = |n| move |_: &PanicHookInfo| CALLS.with(|calls| calls.borrow_mut().push(n));
+        let first = chain_hook(record(1));
+        let second = chain_hook(record(2));
+        catch_unwind_wrapped(|| panic!("test")).unwrap_err();
+        drop(first);
+        catch_unwind_wrapped(|| panic!("test")).unwrap_err();
+        drop(second);
+        catch_unwind_wrapped(|| panic!("test")).unwrap_err();
+        CALLS.with(|calls| assert_eq!(*calls.borrow(), [2, 1, 2]));
+    }
+
+    #[test]
+    fn test_hook_link_send() {
+        let link = chain_hook(|_| ());
+        let link_id = link.id;
+        std::thread::spawn(move || drop(link)).join().unwrap();
+        let links = LINKS.lock().unwrap_or_else(|err| err.into_inner());
+        assert!(links.iter().all(|(id, _)| *id != link_id));
+    }
+}
@@ -0,0 +1,185 @@
+//! Catching panics in iterators
+
+use crate::{catch_unwind_wrapped, MultiPayload, Payload};
+use alloc::vec::Vec;
+use core::{
+    fmt,
+    panic::{AssertUnwindSafe, UnwindSafe},
+};
+
+/// Iterator that maps each item with a closure and catches unwinding panics from it. See
+/// [`IteratorExt::map_caught`].
+#[derive(Clone)]
+#[must_use = "iterators are lazy and do nothing unless consumed"]
+pub struct MapCaught<I, F> {
+    inner: I,
+    f: F,
+}
+
+impl<I: fmt::Debug, F> fmt::Debug for MapCaught<I, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapCaught")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<I: Iterator, F: FnMut(I::Item) -> T + UnwindSafe, T> Iterator for MapCaught<I, F> {
+    type Item = Result<T, Payload>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.inner.next()?;
+        let f = &mut self.f;
+        // `f` is unwind safe, and the item is moved into the closure
+        Some(catch_unwind_wrapped(AssertUnwindSafe(|| f(item))))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Extension trait for catching panics in iterators
+pub trait IteratorExt: Iterator + Sized {
+    /// Map each item with the provided closure, and catch any unwinding panics that may occur
+    /// while mapping an item. A caught panic is yielded as an `Err(Payload)` item, and iteration
+    /// continues with the next item.
+    ///
+    /// ```
+    /// use catch_unwind::iter::IteratorExt;
+    ///
+    /// let results: Vec<_> = ["1", "x", "3"]
+    ///     .into_iter()
+    ///     .map_caught(|s| s.parse::<i32>().unwrap())
+    ///     .map(|result| result.map_err(|payload| payload.drop_or_forget()))
+    ///     .collect();
+    /// assert_eq!(results, [Ok(1), Err(()), Ok(3)]);
+    /// ```
+    ///
+    /// See [`std::panic::catch_unwind`] for more information.
+    #[inline]
+    fn map_caught<F: FnMut(Self::Item) -> T + UnwindSafe, T>(self, f: F) -> MapCaught<Self, F> {
+        MapCaught { inner: self, f }
+    }
+
+    /// Call the provided closure for each item, and catch any unwinding panics that may occur.
+    /// Iteration stops at the first caught panic.
+    ///
+    /// Returns `Ok` if no panics were caught, and `Err` with the index of the item that
+    /// panicked and the payload otherwise.
+    ///
+    /// ```
+    /// use catch_unwind::iter::IteratorExt;
+    ///
+    /// let (index, payload) = [1, 2, 0, 4]
+    ///     .into_iter()
+    ///     .try_for_each_caught(|n| assert_ne!(n, 0))
+    ///     .unwrap_err();
+    /// assert_eq!(index, 2);
+    /// payload.drop_or_forget();
+    /// ```
+    ///
+    /// See [`std::panic::catch_unwind`] for more information.
+    fn try_for_each_caught<F: FnMut(Self::Item) + UnwindSafe>(
+        self,
+        mut f: F,
+    ) -> Result<(), (usize, Payload)> {
+        for (index, item) in self.enumerate() {
+            // `f` is unwind safe, and the item is moved into the closure
+            catch_unwind_wrapped(AssertUnwindSafe(|| f(item)))
+                .map_err(|payload| (index, payload))?;
+        }
+        Ok(())
+    }
+
+    /// Collect the results of caught operations, such as from [`map_caught`](Self::map_caught),
+    /// into the successfully produced items and all the caught payloads.
+    ///
+    /// ```
+    /// use catch_unwind::iter::IteratorExt;
+    ///
+    /// let (values, panics) = ["1", "x", "3", "y"]
+    ///     .into_iter()
+    ///     .map_caught(|s| s.parse::<i32>().unwrap())
+    ///     .collect_caught();
+    /// assert_eq!(values, [1, 3]);
+    /// assert_eq!(panics.len(), 2);
+    /// panics.drop_or_forget();
+    /// ```
+    fn collect_caught<T>(self) -> (Vec<T>, MultiPayload)
+    where
+        Self: Iterator<Item = Result<T, Payload>>,
+    {
+        let mut values = Vec::new();
+        let mut panics = MultiPayload::new();
+        for result in self {
+            match result {
+                Ok(value) => values.push(value),
+                Err(payload) => panics.push(payload),
+            }
+        }
+        (values, panics)
+    }
+}
+
+impl<I: Iterator> IteratorExt for I {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::endless_panic;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_map_caught() {
+        let results: Vec<_> = (0..3)
+            .map_caught(|i| {
+                if i == 1 {
+                    endless_panic()
+                }
+                i
+            })
+            .map(|item| item.map_err(Payload::drop_or_forget))
+            .collect();
+        assert_eq!(results, [Ok(0), Err(()), Ok(2)]);
+    }
+
+    #[test]
+    fn test_try_for_each_caught() {
+        let count = AtomicUsize::new(0);
+        assert!((0..3)
+            .try_for_each_caught(|_| {
+                count.fetch_add(1, Ordering::Relaxed);
+            })
+            .is_ok());
+        assert_eq!(count.load(Ordering::Relaxed), 3);
+
+        let (index, payload) = (0..3)
+            .try_for_each_caught(|i| {
+                if i == 1 {
+                    endless_panic()
+                }
+            })
+            .unwrap_err();
+        assert_eq!(index, 1);
+        payload.drop_or_forget();
+    }
+
+    #[test]
+    fn test_collect_caught() {
+        let (values, panics) = (0..5)
+            .map_caught(|i| {
+                if i % 2 == 1 {
+                    endless_panic()
+                }
+                i
+            })
+            .collect_caught();
+        assert_eq!(values, [0, 2, 4]);
+        assert_eq!(panics.len(), 2);
+        assert_eq!(panics.messages().count(), 0);
+        panics.drop_or_forget();
+    }
+}
@@ -0,0 +1,132 @@
+//! Throwing Java exceptions from caught panics with [`jni`](::jni)
+
+use crate::{catch_unwind_wrapped, thread::default_policy};
+use ::jni::JNIEnv;
+use std::panic::{AssertUnwindSafe, UnwindSafe};
+
+const NON_STRING_MESSAGE: &str = "Rust panic";
+
+/// The parts of a JNI environment that [`catch_unwind_jni`] uses to report a caught panic
+trait ThrowEnv {
+    /// Check if an exception is pending. Errors count as pending, so nothing is thrown over them.
+    fn exception_pending(&mut self) -> bool;
+
+    /// Throw a `java.lang.RuntimeException` with the provided message
+    fn throw_runtime_exception(&mut self, msg: String);
+}
+
+impl ThrowEnv for JNIEnv<'_> {
+    #[inline]
+    fn exception_pending(&mut self) -> bool {
+        self.exception_check().unwrap_or(true)
+    }
+
+    #[inline]
+    fn throw_runtime_exception(&mut self, msg: String) {
+        let _ = self.throw_new("java/lang/RuntimeException", msg);
+    }
+}
+
+/// Invoke the provided closure and catch any unwinding panics that may occur, for use as the
+/// body of a JNI entry point.
+///
+/// If a panic is caught, the payload is dropped according to the current thread's default
+/// policy, a `java.lang.RuntimeException` with the panic message is thrown unless an exception
+/// is already pending, and `R::default()` is returned to the JVM.
+///
+/// ```no_run
+/// use catch_unwind::jni::catch_unwind_jni;
+/// use jni::{objects::JClass, sys::jint, JNIEnv};
+///
+/// #[no_mangle]
+/// pub extern "system" fn Java_Example_half(mut env: JNIEnv, _class: JClass, value: jint) -> jint {
+///     catch_unwind_jni(&mut env, |_env| {
+///         assert!(value % 2 == 0, "value is odd");
+///         value / 2
+///     })
+/// }
+/// ```
+#[inline]
+pub fn catch_unwind_jni<'local, F, R>(env: &mut JNIEnv<'local>, f: F) -> R
+where
+    F: FnOnce(&mut JNIEnv<'local>) -> R + UnwindSafe,
+    R: Default,
+{
+    catch_or_throw(env, f)
+}
+
+fn catch_or_throw<E: ThrowEnv, F: FnOnce(&mut E) -> R + UnwindSafe, R: Default>(
+    env: &mut E,
+    f: F,
+) -> R {
+    let mut env_for_f = AssertUnwindSafe(&mut *env);
+    match catch_unwind_wrapped(move || f(&mut env_for_f)) {
+        Ok(ok) => ok,
+        Err(payload) => {
+            let msg = payload.message().unwrap_or(NON_STRING_MESSAGE).to_owned();
+            payload.drop_with_policy(default_policy());
+            if !env.exception_pending() {
+                env.throw_runtime_exception(msg);
+            }
+            R::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::endless_panic, thread::with_drop_policy, DropPolicy};
+    use std::panic::panic_any;
+
+    #[derive(Default)]
+    struct MockEnv {
+        pending: bool,
+        thrown: Vec<String>,
+    }
+
+    impl ThrowEnv for MockEnv {
+        fn exception_pending(&mut self) -> bool {
+            self.pending
+        }
+
+        fn throw_runtime_exception(&mut self, msg: String) {
+            self.pending = true;
+            self.thrown.push(msg);
+        }
+    }
+
+    #[test]
+    fn test_catch_or_throw() {
+        let mut env = MockEnv::default();
+        assert_eq!(catch_or_throw(&mut env, |_| 1), 1);
+        assert!(env.thrown.is_empty());
+
+        assert_eq!(catch_or_throw(&mut env, |_| -> i32 { panic!("test") }), 0);
+        assert_eq!(env.thrown, ["test"]);
+
+        let mut env = MockEnv::default();
+        assert_eq!(catch_or_throw(&mut env, |_| -> i32 { panic_any(1) }), 0);
+        assert_eq!(env.thrown, [NON_STRING_MESSAGE]);
+
+        let mut env = MockEnv::default();
+        with_drop_policy(DropPolicy::Forget, || {
+            catch_or_throw(&mut env, |_| endless_panic());
+        });
+        assert_eq!(env.thrown, [NON_STRING_MESSAGE]);
+    }
+
+    #[test]
+    fn test_catch_or_throw_pending() {
+        let mut env = MockEnv {
+            pending: true,
+            ..MockEnv::default()
+        };
+        let value = catch_or_throw(&mut env, |env| -> String {
+            assert!(env.exception_pending());
+            panic!("test")
+        });
+        assert_eq!(value, String::new());
+        assert!(env.thrown.is_empty());
+    }
+}
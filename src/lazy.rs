@@ -0,0 +1,95 @@
+//! Lazy initialization that can be retried after a panic
+
+use crate::{catch_unwind_wrapped, Payload};
+use std::{
+    fmt,
+    panic::{AssertUnwindSafe, RefUnwindSafe},
+    sync::OnceLock,
+};
+
+/// Value that's initialized on first access, like [`LazyLock`](std::sync::LazyLock), but with
+/// the initializer run under [`catch_unwind_wrapped`].
+///
+/// If the initializer panics, the value stays uninitialized and the caught payload is returned,
+/// and the next access runs the initializer again, instead of poisoning the value or
+/// propagating the panic. Concurrent accesses wait for the initializer like with
+/// [`OnceLock`].
+///
+/// ```
+/// use catch_unwind::lazy::GuardedLazy;
+/// use std::sync::atomic::{AtomicU32, Ordering};
+///
+/// static ATTEMPTS: AtomicU32 = AtomicU32::new(0);
+/// static CONFIG: GuardedLazy<u32> = GuardedLazy::new(|| {
+///     let attempt = ATTEMPTS.fetch_add(1, Ordering::Relaxed) + 1;
+///     assert!(attempt > 1, "config not ready");
+///     attempt
+/// });
+///
+/// CONFIG.force().unwrap_err().drop_or_forget();
+/// assert_eq!(CONFIG.get(), None);
+/// assert!(matches!(CONFIG.force(), Ok(2)));
+/// ```
+pub struct GuardedLazy<T, F = fn() -> T> {
+    cell: OnceLock<T>,
+    init: F,
+}
+
+impl<T, F> GuardedLazy<T, F> {
+    /// Create a new lazy value with the provided initializer
+    #[inline]
+    pub const fn new(init: F) -> Self {
+        Self {
+            cell: OnceLock::new(),
+            init,
+        }
+    }
+
+    /// Get the value if it's initialized
+    #[inline]
+    pub fn get(&self) -> Option<&T> {
+        self.cell.get()
+    }
+}
+
+impl<T, F: Fn() -> T + RefUnwindSafe> GuardedLazy<T, F> {
+    /// Get the value, initializing it first if it isn't initialized yet.
+    ///
+    /// Returns `Ok` with the value if it's initialized, and `Err(Payload)` if the initializer
+    /// panicked, in which case the value stays uninitialized.
+    #[inline]
+    pub fn force(&self) -> Result<&T, Payload> {
+        // `OnceLock` stays uninitialized if the initializer panics, and the initializer is
+        // unwind safe
+        catch_unwind_wrapped(AssertUnwindSafe(|| self.cell.get_or_init(&self.init)))
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for GuardedLazy<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("GuardedLazy").field(&self.cell).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_guarded_lazy() {
+        let attempts = AtomicU32::new(0);
+        let lazy = GuardedLazy::new(|| {
+            if attempts.fetch_add(1, Ordering::Relaxed) < 2 {
+                crate::tests::endless_panic()
+            }
+            "success"
+        });
+        lazy.force().unwrap_err().drop_or_forget();
+        lazy.force().unwrap_err().drop_or_forget();
+        assert_eq!(lazy.get(), None);
+        assert!(matches!(lazy.force(), Ok(&"success")));
+        assert!(matches!(lazy.force(), Ok(&"success")));
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+    }
+}
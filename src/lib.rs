@@ -1,24 +1,378 @@
 //! This crate provides wrappers for [`std::panic::catch_unwind`] that handle the
 //! edge case of the caught panic payload itself panicing when dropped.
+//!
+//! # Targets without unwinding
+//!
+//! On targets where panics can't unwind, such as `wasm32-unknown-unknown` by default, and in
+//! builds with `-C panic=abort`, the crate still compiles, but panics abort (or trap) instead of
+//! being caught, so the catch functions only ever return their success values and payloads are
+//! never dropped. In that case the catch and drop functions compile down to a plain call of the
+//! closure or drop of the value, so they have no cost. Use [`can_unwind`] to check for this and
+//! branch on it, e.g. to fall back to running work in a separate process.
+//!
+//! # Abort-only builds
+//!
+//! With the `abort-only` feature, any panic caught by this crate aborts the process instead of
+//! being returned or forgotten, including panics from dropping a value. Everything that returns,
+//! forwards or forgets a caught panic is compiled out, along with the drop policies and the
+//! integration modules, so it's guaranteed at compile time that no panic is ever swallowed, for
+//! deployments where that's required. The integration features can't be enabled together with
+//! `abort-only`.
+//!
+//! What remains is [`catch_unwind_or_abort`], [`drop_or_abort`], [`drop_or_exit`],
+//! [`AbortOnUnwind`], [`abort_on_panic!`], [`chain_hook`], [`can_unwind`], and the abort
+//! configuration in [`on_abort`] and [`set_terminator`]. The callbacks registered with
+//! [`on_abort`] are still called.
+//!
+//! # Code size
+//!
+//! The catch and drop functions are thin generic shims around a shared non-generic core. With
+//! the `size-opt` feature, the core is never inlined, so binaries with many catch sites, such as
+//! FFI boundaries, don't get a separate landing pad at each one, at the cost of an extra call.
+//!
+//! # Panic hooks
+//!
+//! The catch, drop and abort functions can be used from inside a panic hook or from a destructor
+//! running while the thread unwinds. Aborting doesn't allocate or lock, and its diagnostic
+//! messages are written to stderr with a single raw write, so it's also safe from inside an
+//! allocator or a signal handler, as long as the callbacks registered with [`on_abort`] are.
+//!
+//! The hook used for capturing panic information can't be installed while the thread is
+//! panicking, so if capturing is first used from a panic hook, that panic isn't captured and the
+//! hook is installed on the next use.
+//!
+//! # `no_std`
+//!
+//! Without the default `std` feature, the crate is `no_std` and requires `alloc`. The `unwinding`
+//! feature must be enabled in that case, and panics are caught and resumed with the
+//! [`unwinding`](https://docs.rs/unwinding) crate, which must be configured by the application
+//! to provide the unwinder and panic runtime. Only [`Payload`], the policies and the catch and
+//! drop functions are available. Aborting calls the panic handler without unwinding, the
+//! process-wide default policy is the only default policy, and [`DropPolicy::Log`] and
+//! [`CatchPolicy::Log`] don't write anything, as there's no stderr.
 
-use std::{
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(any(feature = "std", feature = "unwinding")))]
+compile_error!("either the `std` or the `unwinding` feature must be enabled");
+
+#[cfg(all(
+    feature = "abort-only",
+    any(
+        feature = "crossbeam",
+        feature = "eyre",
+        feature = "futures-core",
+        feature = "jni",
+        feature = "macros",
+        feature = "metrics",
+        feature = "miette",
+        feature = "pyo3",
+        feature = "rayon",
+        feature = "sentry",
+        feature = "test-util",
+        feature = "tokio",
+        feature = "tower",
+        feature = "wasm",
+    )
+))]
+compile_error!(
+    "the `abort-only` feature can't be combined with features that return caught panics"
+);
+
+extern crate alloc;
+
+use alloc::{boxed::Box, string::String};
+use core::{
     any::Any,
     mem,
-    panic::{catch_unwind, resume_unwind, AssertUnwindSafe, UnwindSafe},
-    process::abort,
+    panic::{AssertUnwindSafe, UnwindSafe},
+};
+use rt::{abort, catch_unwind};
+
+#[cfg(not(feature = "abort-only"))]
+use alloc::vec::Vec;
+#[cfg(not(feature = "abort-only"))]
+use core::{
+    any::TypeId,
+    ffi::c_void,
+    fmt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+#[cfg(not(feature = "abort-only"))]
+use rt::resume_unwind;
+
+#[cfg(test)]
+extern crate self as catch_unwind;
+
+mod rt;
+
+#[cfg(feature = "std")]
+mod abort;
+
+#[cfg(all(feature = "std", not(feature = "abort-only")))]
+mod capture;
+
+#[cfg(not(feature = "abort-only"))]
+pub mod cell;
+
+#[cfg(all(feature = "std", not(feature = "abort-only")))]
+pub mod channel;
+
+#[cfg(not(feature = "abort-only"))]
+pub mod cleanup;
+
+#[cfg(not(feature = "abort-only"))]
+pub mod collections;
+
+#[cfg(all(feature = "std", not(feature = "abort-only")))]
+mod config;
+
+#[cfg(all(feature = "std", not(feature = "abort-only")))]
+pub mod ffi;
+
+#[cfg(all(feature = "std", not(feature = "abort-only")))]
+pub mod future;
+
+#[cfg(feature = "std")]
+mod hook;
+
+#[cfg(not(feature = "abort-only"))]
+pub mod iter;
+
+#[cfg(all(feature = "std", not(feature = "abort-only")))]
+pub mod lazy;
+
+#[cfg(all(feature = "std", not(feature = "abort-only")))]
+mod macros;
+
+#[cfg(not(feature = "abort-only"))]
+pub mod option;
+
+#[cfg(all(feature = "std", not(feature = "abort-only")))]
+pub mod process;
+
+#[cfg(all(feature = "std", not(feature = "abort-only")))]
+mod report;
+
+#[cfg(not(feature = "abort-only"))]
+pub mod result;
+
+#[cfg(not(feature = "abort-only"))]
+pub mod slot;
+
+#[cfg(all(feature = "std", not(feature = "abort-only")))]
+pub mod supervisor;
+
+#[cfg(all(feature = "std", not(feature = "abort-only")))]
+pub mod thread;
+
+#[cfg(all(feature = "std", not(feature = "abort-only")))]
+pub mod worker;
+
+#[cfg(all(feature = "futures", not(feature = "abort-only")))]
+pub mod sink;
+
+#[cfg(all(feature = "futures-core", not(feature = "abort-only")))]
+pub mod stream;
+
+#[cfg(all(feature = "tokio", not(feature = "abort-only")))]
+pub mod tokio;
+
+#[cfg(all(feature = "tower", not(feature = "abort-only")))]
+pub mod tower;
+
+#[cfg(all(feature = "crossbeam", not(feature = "abort-only")))]
+pub mod crossbeam;
+
+#[cfg(all(feature = "eyre", not(feature = "abort-only")))]
+pub mod eyre;
+
+#[cfg(all(feature = "jni", not(feature = "abort-only")))]
+pub mod jni;
+
+#[cfg(all(feature = "metrics", not(feature = "abort-only")))]
+pub mod metrics;
+
+#[cfg(all(feature = "miette", not(feature = "abort-only")))]
+pub mod miette;
+
+#[cfg(all(feature = "pyo3", not(feature = "abort-only")))]
+pub mod pyo3;
+
+#[cfg(all(feature = "rayon", not(feature = "abort-only")))]
+pub mod rayon;
+
+#[cfg(all(feature = "sentry", not(feature = "abort-only")))]
+pub mod sentry;
+
+#[cfg(all(feature = "test-util", not(feature = "abort-only")))]
+pub mod testing;
+
+#[cfg(all(feature = "wasm", not(feature = "abort-only")))]
+pub mod wasm;
+
+#[cfg(all(feature = "macros", not(feature = "abort-only")))]
+pub use catch_unwind_macros::{catch_unwind, ffi_catch_unwind};
+
+#[cfg(feature = "std")]
+pub use abort::{on_abort, set_terminator, terminator, AbortOnUnwind, Terminator};
+#[cfg(all(feature = "std", not(feature = "abort-only")))]
+pub use capture::{
+    assert_unwind_safe, assert_unwind_safe_because, catch_unwind_captured,
+    set_verbose_drop_diagnostics, verbose_drop_diagnostics, CatchScope, CaughtPanic, PanicLocation,
+    UnwindSafetyAssertion,
 };
+#[cfg(all(feature = "std", not(feature = "abort-only")))]
+pub use config::{catch_unwind_with_config, CatchConfig};
+#[cfg(feature = "std")]
+pub use hook::{chain_hook, HookLink};
+
+#[doc(hidden)]
+#[cfg(all(feature = "std", not(feature = "abort-only")))]
+pub use macros::{__PanicMessage, __assert_no_panic, __assert_panicked};
+#[cfg(all(feature = "std", not(feature = "abort-only")))]
+pub use report::{PanicReporter, Report, StderrReporter};
+
+/// What to do when a caught panic payload panics when dropped
+#[cfg(not(feature = "abort-only"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DropPolicy {
+    /// Write a message to stderr, then abort the process
+    #[default]
+    Abort,
+
+    /// `mem::forget` the new panic payload
+    Forget,
+
+    /// Write a message to stderr, then `mem::forget` the new panic payload
+    Log,
+}
+
+#[cfg(not(feature = "abort-only"))]
+impl DropPolicy {
+    #[inline]
+    const fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::Abort,
+            1 => Self::Forget,
+            _ => Self::Log,
+        }
+    }
 
-/// Unwinding payload wrapped to abort by default if it panics on drop
+    #[cfg(feature = "std")]
+    fn from_env_value(value: &str) -> Option<Self> {
+        let value = value.trim();
+        [Self::Abort, Self::Forget, Self::Log]
+            .into_iter()
+            .find(|policy| policy.env_value().eq_ignore_ascii_case(value))
+    }
+
+    #[cfg(feature = "std")]
+    const fn env_value(self) -> &'static str {
+        match self {
+            Self::Abort => "abort",
+            Self::Forget => "forget",
+            Self::Log => "log",
+        }
+    }
+}
+
+/// What to do when a panic is caught, before its payload is dropped. What to do if the payload
+/// then panics when dropped is configured separately with [`DropPolicy`], as a second panic is
+/// usually more severe.
+#[cfg(not(feature = "abort-only"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CatchPolicy {
+    /// Handle the panic without any further action
+    #[default]
+    Ignore,
+
+    /// Write the panic message to stderr
+    Log,
+
+    /// Abort the process
+    Abort,
+}
+
+/// Environment variable that selects the initial process-wide default policy. See
+/// [`default_drop_policy`].
+#[cfg(not(feature = "abort-only"))]
+pub const POLICY_ENV_VAR: &str = "CATCH_UNWIND_POLICY";
+
+#[cfg(not(feature = "abort-only"))]
+const UNINIT_POLICY: u8 = u8::MAX;
+
+#[cfg(not(feature = "abort-only"))]
+static DEFAULT_DROP_POLICY: AtomicU8 = AtomicU8::new(UNINIT_POLICY);
+
+/// Set the process-wide default policy for handling a panic payload that panics when dropped.
+///
+/// This is used when dropping a [`Payload`] and by the other functions that use a default
+/// policy, unless the current thread has its own default policy (see
+/// [`thread::set_default_policy`]). It's [`DropPolicy::Abort`] unless changed.
+#[cfg(not(feature = "abort-only"))]
+#[inline]
+pub fn set_default_drop_policy(policy: DropPolicy) {
+    DEFAULT_DROP_POLICY.store(policy as u8, Ordering::Relaxed)
+}
+
+/// Get the process-wide default policy for handling a panic payload that panics when dropped.
+/// See [`set_default_drop_policy`].
+///
+/// If the default policy hasn't been set, it's selected the first time it's used from the
+/// `CATCH_UNWIND_POLICY` environment variable, which can be `abort`, `forget` or `log`. This
+/// lets operators change how a deployed binary handles payloads that panic when dropped
+/// without rebuilding it. If the variable isn't set or has another value, the default is
+/// [`DropPolicy::Abort`].
+#[cfg(not(feature = "abort-only"))]
+#[inline]
+pub fn default_drop_policy() -> DropPolicy {
+    match DEFAULT_DROP_POLICY.load(Ordering::Relaxed) {
+        UNINIT_POLICY => init_default_drop_policy(),
+        policy => DropPolicy::from_u8(policy),
+    }
+}
+
+#[cfg(not(feature = "abort-only"))]
+#[cold]
+fn init_default_drop_policy() -> DropPolicy {
+    let policy = rt::env_policy().unwrap_or_default();
+    match DEFAULT_DROP_POLICY.compare_exchange(
+        UNINIT_POLICY,
+        policy as u8,
+        Ordering::Relaxed,
+        Ordering::Relaxed,
+    ) {
+        Ok(_) => policy,
+        Err(policy) => DropPolicy::from_u8(policy),
+    }
+}
+
+/// Unwinding payload wrapped to abort by default if it panics on drop.
+///
+/// When dropped, a panic on drop is handled according to the current thread's default policy
+/// (see [`thread::default_policy`]), which is [`DropPolicy::Abort`] unless changed.
+#[cfg(not(feature = "abort-only"))]
 pub struct Payload(Option<Box<dyn Any + Send + 'static>>);
 
+#[cfg(not(feature = "abort-only"))]
 impl Payload {
+    /// Wrap a payload that was caught without this crate's catch functions, e.g. by a runtime
+    #[cfg_attr(not(any(feature = "crossbeam", feature = "tokio")), allow(dead_code))]
+    #[inline]
+    pub(crate) fn new(payload: Box<dyn Any + Send + 'static>) -> Self {
+        #[cfg(feature = "metrics")]
+        metrics::panic_caught();
+        Self(Some(caught(payload)))
+    }
+
     /// Get a reference to the payload
     #[inline]
     pub fn get(&self) -> &(dyn Any + Send + 'static) {
         let Some(payload) = &self.0 else {
             unreachable!()
         };
-        payload
+        &**payload
     }
 
     /// Get a mutable reference to the payload
@@ -27,7 +381,48 @@ impl Payload {
         let Some(payload) = &mut self.0 else {
             unreachable!()
         };
-        payload
+        &mut **payload
+    }
+
+    /// Get the panic message, if the payload is a string
+    #[inline]
+    pub fn message(&self) -> Option<&str> {
+        payload_message(self.get())
+    }
+
+    /// Get what kind of value the payload is, for matching on it
+    ///
+    /// ```
+    /// use catch_unwind::{catch_unwind_wrapped, PayloadKind};
+    ///
+    /// let payload = catch_unwind_wrapped(|| panic!("oh {}", "no")).unwrap_err();
+    /// match payload.kind() {
+    ///     PayloadKind::StaticStr(msg) => println!("static message: {msg}"),
+    ///     PayloadKind::String(msg) => assert_eq!(msg, "oh no"),
+    ///     PayloadKind::Other { .. } => println!("custom payload"),
+    /// }
+    /// ```
+    #[inline]
+    pub fn kind(&self) -> PayloadKind<'_> {
+        let payload = self.get();
+        if let Some(s) = payload.downcast_ref::<&'static str>() {
+            PayloadKind::StaticStr(s)
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            PayloadKind::String(s)
+        } else {
+            PayloadKind::Other {
+                type_id: payload.type_id(),
+            }
+        }
+    }
+
+    /// Take the payload out as a value of type `T`, or get it back unchanged if it has another
+    /// type
+    #[inline]
+    pub fn downcast<T: Any>(self) -> Result<Box<T>, Self> {
+        self.into_inner()
+            .downcast()
+            .map_err(|payload| Self(Some(payload)))
     }
 
     /// Get the payload itself. This may panic when dropped
@@ -42,12 +437,75 @@ impl Payload {
         drop_or_abort(self.into_inner())
     }
 
+    /// Drop the payload and exit the process with `code` if doing so panics. See
+    /// [`drop_or_exit`].
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn drop_or_exit(self, code: i32) {
+        drop_or_exit(self.into_inner(), code)
+    }
+
     /// Drop the payload. If doing so panics, `mem::forget` the new payload
     #[inline]
     pub fn drop_or_forget(self) {
         drop_or_forget(self.into_inner())
     }
 
+    /// Drop the payload, handling a panic on drop according to `policy`. With
+    /// [`DropPolicy::Log`], the message describes both this payload and the panic from dropping
+    /// it, like [`DoublePanic`].
+    #[inline]
+    pub fn drop_with_policy(self, policy: DropPolicy) {
+        match policy {
+            DropPolicy::Log => self.drop_or_log(),
+            policy => drop_with_policy(self.into_inner(), policy),
+        }
+    }
+
+    /// Drop the payload. If doing so panics, return the new panic payload together with a
+    /// summary of this payload, in [`DoublePanic`].
+    ///
+    /// ```
+    /// use catch_unwind::catch_unwind_wrapped;
+    /// use std::panic::panic_any;
+    ///
+    /// struct Bomb;
+    ///
+    /// impl Drop for Bomb {
+    ///     fn drop(&mut self) {
+    ///         panic!("boom");
+    ///     }
+    /// }
+    ///
+    /// let payload = catch_unwind_wrapped(|| panic_any(Bomb)).unwrap_err();
+    /// let double = payload.drop_checked().unwrap_err();
+    /// assert_eq!(double.secondary().message(), Some("boom"));
+    /// ```
+    #[inline]
+    pub fn drop_checked(self) -> Result<(), DoublePanic> {
+        let original = self.summary();
+        drop_wrapped(self.into_inner()).map_err(|secondary| DoublePanic {
+            original,
+            secondary,
+            location: rt::drop_location(),
+        })
+    }
+
+    /// Get a summary of the payload, which can be kept after the payload is gone
+    #[inline]
+    pub fn summary(&self) -> PayloadSummary {
+        PayloadSummary::of(self.get())
+    }
+
+    /// Drop the payload. If doing so panics, write a message describing both panics to stderr
+    /// and `mem::forget` the new panic payload
+    fn drop_or_log(self) {
+        if let Err(double) = self.drop_checked() {
+            rt::log(format_args!("catch-unwind: {double}"));
+            forget_payload(double.secondary.into_inner())
+        }
+    }
+
     /// Resume unwinding with this payload
     #[inline]
     pub fn resume_unwind(self) {
@@ -55,13 +513,335 @@ impl Payload {
     }
 }
 
+#[cfg(not(feature = "abort-only"))]
 impl Drop for Payload {
     #[inline]
     fn drop(&mut self) {
         if let Some(payload) = self.0.take() {
-            drop_or_abort(payload)
+            Self(Some(payload)).drop_with_policy(rt::default_policy())
+        }
+    }
+}
+
+/// Kind of value a panic payload is. See [`Payload::kind`].
+#[cfg(not(feature = "abort-only"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PayloadKind<'a> {
+    /// A `&'static str`, as raised by `panic!` with a literal message
+    StaticStr(&'static str),
+
+    /// A `String`, as raised by `panic!` with a formatted message
+    String(&'a str),
+
+    /// Any other type, e.g. raised with `panic_any`. The type's name isn't available at runtime,
+    /// but its id can be compared with [`TypeId::of`].
+    Other {
+        /// Id of the payload's type
+        type_id: TypeId,
+    },
+}
+
+#[cfg(not(feature = "abort-only"))]
+impl<'a> PayloadKind<'a> {
+    /// Get the panic message, if the payload is a string
+    #[inline]
+    pub fn message(self) -> Option<&'a str> {
+        match self {
+            Self::StaticStr(s) => Some(s),
+            Self::String(s) => Some(s),
+            Self::Other { .. } => None,
+        }
+    }
+}
+
+/// Summary of a panic payload, which can be kept after the payload is gone, e.g. because it
+/// panicked when dropped
+#[cfg(not(feature = "abort-only"))]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PayloadSummary {
+    message: Option<Box<str>>,
+    type_id: TypeId,
+}
+
+#[cfg(not(feature = "abort-only"))]
+impl PayloadSummary {
+    #[inline]
+    pub(crate) fn of(payload: &(dyn Any + Send + 'static)) -> Self {
+        Self {
+            message: payload_message(payload).map(Box::from),
+            type_id: payload.type_id(),
         }
     }
+
+    /// Get the panic message, if the payload was a string
+    #[inline]
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// Get the type id of the payload
+    #[inline]
+    pub fn type_id(&self) -> TypeId {
+        self.type_id
+    }
+}
+
+#[cfg(not(feature = "abort-only"))]
+impl fmt::Display for PayloadSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message().unwrap_or("Box<dyn Any>"))
+    }
+}
+
+/// Panic payload that panicked when dropped, with a summary of the original payload and the
+/// payload of the panic from its destructor. See [`Payload::drop_checked`].
+#[cfg(not(feature = "abort-only"))]
+pub struct DoublePanic {
+    original: PayloadSummary,
+    secondary: Payload,
+    location: rt::DropLocation,
+}
+
+#[cfg(not(feature = "abort-only"))]
+impl DoublePanic {
+    /// Get a summary of the payload that panicked when dropped
+    #[inline]
+    pub fn original(&self) -> &PayloadSummary {
+        &self.original
+    }
+
+    /// Get the payload of the panic from the original payload's destructor
+    #[inline]
+    pub fn secondary(&self) -> &Payload {
+        &self.secondary
+    }
+
+    /// Get the location of the panic from the original payload's destructor, if it was
+    /// captured. It's only captured when verbose drop diagnostics are enabled; see
+    /// [`set_verbose_drop_diagnostics`].
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn location(&self) -> Option<&PanicLocation> {
+        self.location.0.as_ref()
+    }
+
+    /// Get the payload of the panic from the original payload's destructor
+    #[inline]
+    pub fn into_secondary(self) -> Payload {
+        self.secondary
+    }
+}
+
+#[cfg(not(feature = "abort-only"))]
+impl fmt::Debug for DoublePanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DoublePanic")
+            .field("original", &self.original)
+            .field("secondary", &self.secondary.summary())
+            .finish()
+    }
+}
+
+#[cfg(not(feature = "abort-only"))]
+impl fmt::Display for DoublePanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "panic payload panicked when dropped{}: {}; original panic: {}",
+            self.location,
+            self.secondary.message().unwrap_or("Box<dyn Any>"),
+            self.original
+        )
+    }
+}
+
+/// Collection of unwinding payloads caught from several operations, each wrapped in
+/// [`Payload`]
+#[cfg(not(feature = "abort-only"))]
+#[derive(Default)]
+pub struct MultiPayload(Vec<Payload>);
+
+#[cfg(not(feature = "abort-only"))]
+impl MultiPayload {
+    /// Create an empty collection
+    #[inline]
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Add a payload to the collection
+    #[inline]
+    pub fn push(&mut self, payload: Payload) {
+        self.0.push(payload)
+    }
+
+    /// Get the number of payloads
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Check if there are no payloads, i.e. no panics were caught
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterate over the payloads
+    #[inline]
+    pub fn iter(&self) -> core::slice::Iter<'_, Payload> {
+        self.0.iter()
+    }
+
+    /// Iterate over the panic messages of the payloads that are strings
+    #[inline]
+    pub fn messages(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().filter_map(Payload::message)
+    }
+
+    /// Get the payloads
+    #[inline]
+    pub fn into_vec(self) -> Vec<Payload> {
+        self.0
+    }
+
+    /// Drop the payloads. If dropping any of them panics, `mem::forget` the new payload
+    #[inline]
+    pub fn drop_or_forget(self) {
+        self.0.into_iter().for_each(Payload::drop_or_forget)
+    }
+
+    /// Drop the payloads, handling a panic on drop according to `policy`
+    #[inline]
+    pub fn drop_with_policy(self, policy: DropPolicy) {
+        for payload in self.0 {
+            payload.drop_with_policy(policy)
+        }
+    }
+}
+
+#[cfg(not(feature = "abort-only"))]
+impl From<Vec<Payload>> for MultiPayload {
+    #[inline]
+    fn from(payloads: Vec<Payload>) -> Self {
+        Self(payloads)
+    }
+}
+
+#[cfg(not(feature = "abort-only"))]
+impl FromIterator<Payload> for MultiPayload {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = Payload>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+#[cfg(not(feature = "abort-only"))]
+impl Extend<Payload> for MultiPayload {
+    #[inline]
+    fn extend<I: IntoIterator<Item = Payload>>(&mut self, iter: I) {
+        self.0.extend(iter)
+    }
+}
+
+#[cfg(not(feature = "abort-only"))]
+impl IntoIterator for MultiPayload {
+    type Item = Payload;
+    type IntoIter = alloc::vec::IntoIter<Payload>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+#[cfg(not(feature = "abort-only"))]
+impl<'a> IntoIterator for &'a MultiPayload {
+    type Item = &'a Payload;
+    type IntoIter = core::slice::Iter<'a, Payload>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// Handle a newly caught panic payload. With the `abort-only` feature, this aborts the process.
+#[cfg(not(feature = "abort-only"))]
+#[inline(always)]
+fn caught(payload: Box<dyn Any + Send + 'static>) -> Box<dyn Any + Send + 'static> {
+    #[cfg(feature = "test-util")]
+    testing::count_caught();
+    payload
+}
+
+/// Handle a newly caught panic payload. With the `abort-only` feature, this aborts the process,
+/// so no caught payload ever reaches the caller.
+#[cfg(feature = "abort-only")]
+#[cold]
+fn caught(payload: Box<dyn Any + Send + 'static>) -> ! {
+    mem::forget(payload);
+    abort()
+}
+
+/// Call [`std::panic::catch_unwind`] and pass any caught payload to [`caught`]. This is used for
+/// catching panics other than from dropping a value.
+///
+/// This is a thin shim that erases the closure and calls [`catch_dyn`], so that the catching
+/// itself isn't instantiated for every closure type.
+#[inline]
+fn catch<F: FnOnce() -> R + UnwindSafe, R>(f: F) -> Result<R, Box<dyn Any + Send + 'static>> {
+    let mut f = Some(f);
+    let mut result = None;
+    catch_dyn(&mut || result = f.take().map(|f| f()))?;
+    match result {
+        Some(result) => Ok(result),
+        None => unreachable!(),
+    }
+}
+
+/// Non-generic core of [`catch`]. The closure is only called once.
+fn catch_dyn(f: &mut dyn FnMut()) -> Result<(), Box<dyn Any + Send + 'static>> {
+    // the closure only captures an `UnwindSafe` closure and the slot for its result, which is
+    // only written if it doesn't panic
+    trampoline(f).map_err(|payload| {
+        #[cfg(feature = "metrics")]
+        metrics::panic_caught();
+        caught(payload)
+    })
+}
+
+/// Non-generic core of [`drop_or_else_checked`]. The closure is only called once.
+fn drop_dyn(drop: &mut dyn FnMut()) -> Result<(), Box<dyn Any + Send + 'static>> {
+    // the closure only captures an `UnwindSafe` value to drop
+    rt::with_drop_capture(|| trampoline(drop)).map_err(|payload| {
+        #[cfg(feature = "metrics")]
+        metrics::drop_panicked();
+        caught(payload)
+    })
+}
+
+/// Catch panics from a type-erased closure. This is the only place the catch and drop functions
+/// call [`catch_unwind`]. With the `size-opt` feature, it's never inlined, so there's a single
+/// landing pad shared by every catch site instead of one per site.
+#[cfg_attr(feature = "size-opt", inline(never))]
+fn trampoline(f: &mut dyn FnMut()) -> Result<(), Box<dyn Any + Send + 'static>> {
+    catch_unwind(AssertUnwindSafe(f))
+}
+
+/// Check if panics unwind with the panic strategy this crate was built with. This is `false`
+/// with the `abort` panic strategy, which is used with `-C panic=abort` and is the default on
+/// `wasm32-unknown-unknown`, and in that case panics abort the process (or trap) instead of
+/// being caught.
+#[inline]
+pub const fn can_unwind() -> bool {
+    cfg!(panic = "unwind")
+}
+
+/// Check if panics can be caught on this target. This is the same as [`can_unwind`].
+#[inline]
+pub const fn supports_catch() -> bool {
+    can_unwind()
 }
 
 /// Invoke the provided closure and catch any unwinding panics that may occur. If the panic
@@ -73,7 +853,7 @@ impl Drop for Payload {
 #[inline]
 #[must_use]
 pub fn catch_unwind_or_abort<F: FnOnce() -> R + UnwindSafe, R>(f: F) -> Option<R> {
-    match catch_unwind(f) {
+    match catch(f) {
         Ok(ok) => Some(ok),
         Err(err) => {
             drop_or_abort(err);
@@ -88,10 +868,11 @@ pub fn catch_unwind_or_abort<F: FnOnce() -> R + UnwindSafe, R>(f: F) -> Option<R
 /// Returns `Some` if no panics were caught and `None` otherwise.
 ///
 /// See [`std::panic::catch_unwind`] for more information.
+#[cfg(not(feature = "abort-only"))]
 #[inline]
 #[must_use]
 pub fn catch_unwind_or_forget<F: FnOnce() -> R + UnwindSafe, R>(f: F) -> Option<R> {
-    match catch_unwind(f) {
+    match catch(f) {
         Ok(ok) => Some(ok),
         Err(err) => {
             drop_or_forget(err);
@@ -100,64 +881,583 @@ pub fn catch_unwind_or_forget<F: FnOnce() -> R + UnwindSafe, R>(f: F) -> Option<
     }
 }
 
+/// Invoke the provided closure and catch any unwinding panics that may occur. If the panic
+/// payload panics when dropped, handle it according to `policy`.
+///
+/// Returns `Some` if no panics were caught and `None` otherwise.
+///
+/// See [`std::panic::catch_unwind`] for more information.
+#[cfg(not(feature = "abort-only"))]
+#[inline]
+#[must_use]
+pub fn catch_unwind_with_policy<F: FnOnce() -> R + UnwindSafe, R>(
+    policy: DropPolicy,
+    f: F,
+) -> Option<R> {
+    match catch(f) {
+        Ok(ok) => Some(ok),
+        Err(err) => {
+            Payload(Some(err)).drop_with_policy(policy);
+            None
+        }
+    }
+}
+
+/// Invoke the provided closure and catch any unwinding panics that may occur. The caught panic
+/// is handled according to `catch_policy`, and if its payload panics when dropped, that's handled
+/// according to `drop_policy`.
+///
+/// Returns `Some` if no panics were caught and `None` otherwise.
+///
+/// See [`std::panic::catch_unwind`] for more information.
+#[cfg(not(feature = "abort-only"))]
+#[inline]
+#[must_use]
+pub fn catch_unwind_with_policies<F: FnOnce() -> R + UnwindSafe, R>(
+    catch_policy: CatchPolicy,
+    drop_policy: DropPolicy,
+    f: F,
+) -> Option<R> {
+    match catch(f) {
+        Ok(ok) => Some(ok),
+        Err(err) => {
+            handle_caught(&*err, catch_policy);
+            Payload(Some(err)).drop_with_policy(drop_policy);
+            None
+        }
+    }
+}
+
 /// Invoke the provided closure and catch any unwinding panics that may occur. This wraps
 /// the unwinding payload in [`Payload`], which will abort if it panics on drop by default.
 /// You can use the methods of `Payload` to change this behaviour.
 ///
-/// Returns `Ok` if no panics were caught and `Err(Payload)` otherwise.
+/// Returns `Ok` if no panics were caught and `Err(Payload)` otherwise. If [`can_unwind`] is
+/// `false`, this always returns `Ok`, as panics abort instead.
 ///
 /// See [`std::panic::catch_unwind`] for more information.
+#[cfg(not(feature = "abort-only"))]
 #[inline]
 pub fn catch_unwind_wrapped<F: FnOnce() -> R + UnwindSafe, R>(f: F) -> Result<R, Payload> {
-    catch_unwind(f).map_err(|e| Payload(Some(e)))
+    catch(f).map_err(|e| Payload(Some(e)))
+}
+
+/// Invoke the provided function and catch any unwinding panics that may occur, like
+/// [`catch_unwind_wrapped`]. This is only instantiated once per return type, rather than once
+/// per closure type, for plugin loaders and FFI layers that only deal in function pointers.
+///
+/// Returns `Ok` if no panics were caught and `Err(Payload)` otherwise.
+///
+/// See [`std::panic::catch_unwind`] for more information.
+#[cfg(not(feature = "abort-only"))]
+#[inline]
+pub fn catch_unwind_fn<R>(f: fn() -> R) -> Result<R, Payload> {
+    catch_unwind_wrapped(f)
+}
+
+/// Invoke the provided function with `data`, and catch any unwinding panics that may occur,
+/// like [`catch_unwind_wrapped`]. This isn't generic, so there's a single copy of it however
+/// many functions it's used with.
+///
+/// Returns `Ok` if no panics were caught and `Err(Payload)` otherwise.
+///
+/// ```
+/// use catch_unwind::catch_unwind_fn_raw;
+/// use std::ffi::c_void;
+///
+/// fn increment(data: *mut c_void) {
+///     // SAFETY: `data` points to an `i32`
+///     unsafe { *data.cast::<i32>() += 1 };
+/// }
+///
+/// let mut value = 1;
+/// assert!(catch_unwind_fn_raw(increment, (&mut value as *mut i32).cast()).is_ok());
+/// assert_eq!(value, 2);
+/// ```
+///
+/// See [`std::panic::catch_unwind`] for more information.
+#[cfg(not(feature = "abort-only"))]
+pub fn catch_unwind_fn_raw(f: fn(*mut c_void), data: *mut c_void) -> Result<(), Payload> {
+    // a function pointer and a raw pointer are unwind safe
+    catch_dyn(&mut || f(data)).map_err(|payload| Payload(Some(payload)))
+}
+
+/// Invoke the provided boxed closure and catch any unwinding panics that may occur, like
+/// [`catch_unwind_wrapped`]. This is only instantiated once per return type, so task queues
+/// and plugin systems that box their jobs share one copy of it across all job types.
+///
+/// The closure is treated as unwind safe, like with [`AssertUnwindSafe`], as it's consumed and
+/// can't be observed after a panic. Shared state that it captures by reference still can be.
+///
+/// Returns `Ok` if no panics were caught and `Err(Payload)` otherwise.
+///
+/// ```
+/// use catch_unwind::catch_unwind_boxed;
+///
+/// let jobs: Vec<Box<dyn FnOnce() + Send>> = vec![Box::new(|| ()), Box::new(|| panic!("oh no"))];
+/// let failed = jobs
+///     .into_iter()
+///     .map(catch_unwind_boxed)
+///     .filter(Result::is_err)
+///     .count();
+/// assert_eq!(failed, 1);
+/// ```
+///
+/// See [`std::panic::catch_unwind`] for more information.
+#[cfg(not(feature = "abort-only"))]
+#[inline]
+pub fn catch_unwind_boxed<R>(f: Box<dyn FnOnce() -> R + Send + '_>) -> Result<R, Payload> {
+    catch_unwind_wrapped(AssertUnwindSafe(f))
+}
+
+/// Invoke the provided closure by mutable reference and catch any unwinding panics that may
+/// occur, like [`catch_unwind_wrapped`]. This is only instantiated once per return type. See
+/// [`catch_unwind_boxed`].
+///
+/// The closure is treated as unwind safe, like with [`AssertUnwindSafe`]. It can be called
+/// again after a panic, so it must not be left in a broken state by one.
+///
+/// Returns `Ok` if no panics were caught and `Err(Payload)` otherwise.
+///
+/// See [`std::panic::catch_unwind`] for more information.
+#[cfg(not(feature = "abort-only"))]
+#[inline]
+pub fn catch_unwind_dyn_mut<R>(f: &mut dyn FnMut() -> R) -> Result<R, Payload> {
+    catch_unwind_wrapped(AssertUnwindSafe(f))
+}
+
+/// Invoke the provided closure with a mutable reference to an accumulator, and catch any
+/// unwinding panics that may occur. Whatever the closure wrote to the accumulator before
+/// panicking is still available afterwards. The caught payload is wrapped in [`Payload`], which
+/// will abort if it panics on drop by default.
+///
+/// The accumulator can be observed after a panic, so it's only passed to the closure by
+/// mutable reference, which is the one capture that isn't unwind safe. It's left in whatever
+/// state the closure's last completed operation put it in, which is valid for the standard
+/// collections, as their methods don't leave them broken if they panic.
+///
+/// Returns `Ok` if no panics were caught and `Err(Payload)` otherwise.
+///
+/// ```
+/// use catch_unwind::catch_unwind_partial;
+///
+/// let mut parsed = Vec::new();
+/// let result = catch_unwind_partial(&mut parsed, |parsed| {
+///     for s in ["1", "2", "x", "4"] {
+///         parsed.push(s.parse::<i32>().unwrap());
+///     }
+/// });
+/// result.unwrap_err().drop_or_forget();
+/// assert_eq!(parsed, [1, 2]);
+/// ```
+///
+/// See [`std::panic::catch_unwind`] for more information.
+#[cfg(not(feature = "abort-only"))]
+#[inline]
+pub fn catch_unwind_partial<A: ?Sized, F: FnOnce(&mut A) -> R + UnwindSafe, R>(
+    acc: &mut A,
+    f: F,
+) -> Result<R, Payload> {
+    catch_unwind_wrapped(AssertUnwindSafe(|| f(acc)))
+}
+
+/// Take a snapshot of some state, then invoke the provided closure with a mutable reference to
+/// the state and catch any unwinding panics that may occur. If the closure panics, the state is
+/// restored from the snapshot before returning, so it can't be observed half-modified. The
+/// caught payload is wrapped in [`Payload`], which will abort if it panics on drop by default.
+///
+/// The state the closure left behind is dropped according to the current thread's default
+/// policy when it's replaced by the snapshot.
+///
+/// Returns `Ok` if no panics were caught and `Err(Payload)` otherwise.
+///
+/// ```
+/// use catch_unwind::with_checkpoint;
+///
+/// let mut values = vec![1, 2];
+/// let result = with_checkpoint(&mut values, Vec::clone, |values| {
+///     values.push(3);
+///     panic!("oh no")
+/// });
+/// result.unwrap_err().drop_or_forget();
+/// assert_eq!(values, [1, 2]);
+/// ```
+///
+/// See [`std::panic::catch_unwind`] for more information.
+#[cfg(not(feature = "abort-only"))]
+#[inline]
+pub fn with_checkpoint<S, F: FnOnce(&mut S) -> R + UnwindSafe, R>(
+    state: &mut S,
+    snapshot: impl Fn(&S) -> S,
+    f: F,
+) -> Result<R, Payload> {
+    let saved = snapshot(state);
+    // the state is restored from the snapshot if the closure panics
+    match catch_unwind_wrapped(AssertUnwindSafe(|| f(state))) {
+        Ok(ok) => {
+            drop(saved);
+            Ok(ok)
+        }
+        Err(payload) => {
+            drop_with_policy(mem::replace(state, saved), rt::default_policy());
+            Err(payload)
+        }
+    }
+}
+
+/// Invoke two closures in order and catch any unwinding panics that may occur in each of them.
+/// The second closure is invoked even if the first one panics, so paired operations such as
+/// flushing and closing both run, and both failures are reported. The caught payloads are
+/// wrapped in [`Payload`], which will abort if they panic on drop by default.
+///
+/// Returns the result of each closure, with `Err(Payload)` for the ones that panicked.
+///
+/// ```
+/// use catch_unwind::catch_unwind_pair;
+///
+/// let (flushed, closed) = catch_unwind_pair(|| panic!("flush failed"), || "closed");
+/// assert_eq!(flushed.unwrap_err().message(), Some("flush failed"));
+/// assert_eq!(closed.ok(), Some("closed"));
+/// ```
+///
+/// See [`std::panic::catch_unwind`] for more information.
+#[cfg(not(feature = "abort-only"))]
+#[inline]
+pub fn catch_unwind_pair<FA: FnOnce() -> A + UnwindSafe, A, FB: FnOnce() -> B + UnwindSafe, B>(
+    a: FA,
+    b: FB,
+) -> (Result<A, Payload>, Result<B, Payload>) {
+    let a = catch_unwind_wrapped(a);
+    (a, catch_unwind_wrapped(b))
 }
 
 /// Drop a value. If dropping the value results in an unwinding panic, call the provided closure
 /// with the panic payload.
+///
+/// If the closure panics too, the new payload is forgotten and the process is aborted, as there's
+/// nothing left to handle it with.
+///
+/// The value is dropped as if it were wrapped in [`AssertUnwindSafe`], so this works for any
+/// type. Use [`drop_or_else_checked`] to have the compiler check that the value is
+/// [`UnwindSafe`] instead.
+#[cfg(not(feature = "abort-only"))]
 #[inline]
 pub fn drop_or_else<T, F: FnOnce(Box<dyn Any + Send + 'static>) -> E, E>(
     value: T,
     or_else: F,
 ) -> Result<(), E> {
-    catch_unwind(AssertUnwindSafe(move || mem::drop(value))).map_err(or_else)
+    drop_or_else_checked(AssertUnwindSafe(value), or_else)
+}
+
+/// Drop a value that is [`UnwindSafe`]. If dropping the value results in an unwinding panic,
+/// call the provided closure with the panic payload.
+///
+/// This is like [`drop_or_else`], but doesn't assert that the value is unwind safe, so shared
+/// state that the value's drop implementation could leave broken has to be wrapped in
+/// [`AssertUnwindSafe`] explicitly.
+///
+/// If the closure panics too, the new payload is forgotten and the process is aborted, as there's
+/// nothing left to handle it with.
+#[cfg(not(feature = "abort-only"))]
+#[inline]
+pub fn drop_or_else_checked<T: UnwindSafe, F: FnOnce(Box<dyn Any + Send + 'static>) -> E, E>(
+    value: T,
+    or_else: F,
+) -> Result<(), E> {
+    drop_or_handle(value, or_else)
+}
+
+/// Core of [`drop_or_else_checked`], which the other drop functions are built on
+#[inline]
+fn drop_or_handle<T: UnwindSafe, F: FnOnce(Box<dyn Any + Send + 'static>) -> E, E>(
+    value: T,
+    or_else: F,
+) -> Result<(), E> {
+    let mut value = Some(value);
+    drop_dyn(&mut || mem::drop(value.take())).map_err(|payload| {
+        // the handler is consumed, and the process aborts if it panics
+        let err = match catch_unwind(AssertUnwindSafe(|| or_else(payload))) {
+            Ok(err) => err,
+            Err(payload) => handler_panicked(payload),
+        };
+        #[cfg(feature = "metrics")]
+        metrics::abort_avoided();
+        err
+    })
 }
 
-/// Drop a value. If dropping the value results in an unwinding panic, abort the process.
+/// Drop a value. If dropping the value results in an unwinding panic, write a message to stderr
+/// and abort the process. The message includes the new panic's message if it's a string, and
+/// writing it doesn't allocate.
 #[inline]
 pub fn drop_or_abort<T>(value: T) {
-    let _ = drop_or_else(value, |_err| abort());
+    let _ = drop_or_handle(AssertUnwindSafe(value), |err| {
+        log_destructor_panicked(err);
+        abort()
+    });
+}
+
+/// Drop a value. If dropping the value results in an unwinding panic, write a message to stderr
+/// like [`drop_or_abort`], then exit the process with `code` using [`std::process::exit`], after
+/// calling the callbacks registered with [`on_abort`]. Unlike aborting, this runs `atexit`
+/// handlers, e.g. to flush coverage data or logs.
+///
+/// To exit instead of aborting everywhere this crate aborts, use [`set_terminator`] with
+/// [`Terminator::Exit`]. With the `abort-only` feature, the panic aborts the process before it
+/// gets here, so that's the only way to exit.
+#[cfg(feature = "std")]
+#[inline]
+pub fn drop_or_exit<T>(value: T, code: i32) {
+    let _ = drop_or_handle(AssertUnwindSafe(value), |err| {
+        log_destructor_panicked(err);
+        abort::exit(code)
+    });
+}
+
+/// Write a message about a panic from a destructor to stderr, without allocating, and
+/// `mem::forget` its payload
+#[cold]
+fn log_destructor_panicked(payload: Box<dyn Any + Send + 'static>) {
+    let location = rt::drop_location();
+    match payload_message(&*payload) {
+        Some(msg) => rt::log(format_args!(
+            "catch-unwind: payload destructor panicked{location}: {msg}"
+        )),
+        None => rt::log(format_args!(
+            "catch-unwind: payload destructor panicked{location}"
+        )),
+    }
+    mem::forget(payload)
 }
 
 /// Drop a value. If dropping the value results in an unwinding panic, `mem::forget` the panic payload.
+#[cfg(not(feature = "abort-only"))]
 #[inline]
 pub fn drop_or_forget<T>(value: T) {
-    let _ = drop_or_else(value, mem::forget);
+    let _ = drop_or_else(value, forget_payload);
+}
+
+/// Drop a value. If dropping the value results in an unwinding panic, return the panic payload
+/// wrapped in [`Payload`].
+#[cfg(not(feature = "abort-only"))]
+#[inline]
+pub fn drop_wrapped<T>(value: T) -> Result<(), Payload> {
+    drop_or_else(value, |e| Payload(Some(e)))
+}
+
+/// Drop a value. If dropping the value results in an unwinding panic, write a message to stderr
+/// and `mem::forget` the panic payload.
+#[cfg(not(feature = "abort-only"))]
+#[inline]
+pub fn drop_or_log<T>(value: T) {
+    let _ = drop_or_else(value, |err| {
+        let location = rt::drop_location();
+        match payload_message(&*err) {
+            Some(msg) => rt::log(format_args!(
+                "catch-unwind: panic payload panicked when dropped{location}: {msg}"
+            )),
+            None => rt::log(format_args!(
+                "catch-unwind: panic payload panicked when dropped{location}"
+            )),
+        }
+        forget_payload(err)
+    });
+}
+
+/// Forget the payload of a panic from a `drop_or_else` handler and abort the process
+#[cold]
+fn handler_panicked(payload: Box<dyn Any + Send + 'static>) -> ! {
+    rt::log(format_args!(
+        "catch-unwind: drop_or_else handler panicked; aborting"
+    ));
+    forget_payload(payload);
+    abort()
+}
+
+/// `mem::forget` a panic payload that panicked when dropped
+#[inline]
+fn forget_payload(payload: Box<dyn Any + Send + 'static>) {
+    #[cfg(feature = "test-util")]
+    testing::count_forgotten();
+    #[cfg(feature = "metrics")]
+    metrics::payload_forgotten();
+    mem::forget(payload)
+}
+
+/// Handle a caught panic payload according to `policy`
+#[cfg(not(feature = "abort-only"))]
+pub(crate) fn handle_caught(payload: &(dyn Any + Send), policy: CatchPolicy) {
+    match policy {
+        CatchPolicy::Ignore => (),
+        CatchPolicy::Log => match payload_message(payload) {
+            Some(msg) => rt::log(format_args!("catch-unwind: caught panic: {msg}")),
+            None => rt::log(format_args!("catch-unwind: caught panic")),
+        },
+        CatchPolicy::Abort => abort(),
+    }
+}
+
+/// Drop a value, handling an unwinding panic according to `policy`.
+#[cfg(not(feature = "abort-only"))]
+#[inline]
+pub fn drop_with_policy<T>(value: T, policy: DropPolicy) {
+    match policy {
+        DropPolicy::Abort => drop_or_abort(value),
+        DropPolicy::Forget => drop_or_forget(value),
+        DropPolicy::Log => drop_or_log(value),
+    }
+}
+
+/// Get the message of a panic payload, if it's a string
+pub(crate) fn payload_message(payload: &dyn Any) -> Option<&str> {
+    if let Some(s) = payload.downcast_ref::<&'static str>() {
+        Some(s)
+    } else {
+        payload.downcast_ref::<String>().map(String::as_str)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(not(feature = "abort-only"))]
     use std::panic::panic_any;
 
-    fn endless_panic() {
-        struct PanicOnDrop;
+    /// Value that panics with another `Bomb` as the payload when dropped, so neither it nor any
+    /// of the payloads from dropping it can be dropped without panicking
+    #[cfg(not(feature = "abort-only"))]
+    pub(crate) struct Bomb;
 
-        impl Drop for PanicOnDrop {
-            fn drop(&mut self) {
-                panic_any(Self)
-            }
+    #[cfg(not(feature = "abort-only"))]
+    impl Drop for Bomb {
+        fn drop(&mut self) {
+            endless_panic()
         }
+    }
+
+    #[cfg(not(feature = "abort-only"))]
+    pub(crate) fn endless_panic() {
+        panic_any(Bomb)
+    }
 
-        panic_any(PanicOnDrop)
+    /// Environment variable set when a test is run again by [`run_in_child`]
+    #[cfg(feature = "std")]
+    const CHILD_ENV_VAR: &str = "CATCH_UNWIND_TEST_CHILD";
+
+    /// Check if the current test was started by [`run_in_child`]
+    #[cfg(feature = "std")]
+    pub(crate) fn in_child() -> bool {
+        std::env::var_os(CHILD_ENV_VAR).is_some()
+    }
+
+    /// Command for running the test named `name` again in a child process, for testing code
+    /// that ends the process or changes process-wide state. The test uses [`in_child`] to tell
+    /// which side it's on.
+    #[cfg(feature = "std")]
+    pub(crate) fn child_command(name: &str) -> std::process::Command {
+        let mut command = std::process::Command::new(std::env::current_exe().unwrap());
+        command
+            .args(["--exact", name, "--nocapture", "--test-threads=1"])
+            .env(CHILD_ENV_VAR, "1");
+        command
+    }
+
+    /// Run the test named `name` again in a child process and wait for it to exit. See
+    /// [`child_command`].
+    #[cfg(feature = "std")]
+    pub(crate) fn run_in_child(name: &str) -> std::process::Output {
+        child_command(name).output().unwrap()
+    }
+
+    /// Assert that a test run in a child process ran and passed
+    #[cfg(all(feature = "std", not(feature = "abort-only")))]
+    #[track_caller]
+    pub(crate) fn assert_passed(output: &std::process::Output) {
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("test result: ok. 1 passed"));
+    }
+
+    /// Assert that a test run by [`run_in_child`] ended the process abnormally instead of
+    /// finishing
+    #[cfg(feature = "std")]
+    #[track_caller]
+    pub(crate) fn assert_aborted(output: &std::process::Output) {
+        assert!(!output.status.success());
+        assert!(!String::from_utf8_lossy(&output.stdout).contains("test result"));
     }
 
     #[test]
+    fn test_can_unwind() {
+        const { assert!(can_unwind()) }
+        const { assert!(supports_catch()) }
+    }
+
+    #[test]
+    #[cfg(not(feature = "abort-only"))]
     fn test_catch_unwind_or_forget() {
         assert_eq!(catch_unwind_or_forget(|| "success"), Some("success"));
         assert_eq!(catch_unwind_or_forget(endless_panic), None);
     }
 
     #[test]
+    #[cfg(all(feature = "std", not(feature = "abort-only")))]
+    fn test_default_drop_policy() {
+        // the process-wide default is changed in a child process, so other tests aren't affected
+        if in_child() {
+            assert_eq!(default_drop_policy(), DropPolicy::Forget);
+            for policy in [DropPolicy::Log, DropPolicy::Abort, DropPolicy::Forget] {
+                set_default_drop_policy(policy);
+                assert_eq!(default_drop_policy(), policy);
+            }
+            return;
+        }
+        let output = child_command("tests::test_default_drop_policy")
+            .env(POLICY_ENV_VAR, "forget")
+            .output()
+            .unwrap();
+        assert_passed(&output);
+    }
+
+    #[test]
+    #[cfg(not(feature = "abort-only"))]
+    fn test_policy_from_env_value() {
+        assert_eq!(DropPolicy::from_env_value("abort"), Some(DropPolicy::Abort));
+        assert_eq!(
+            DropPolicy::from_env_value(" Forget"),
+            Some(DropPolicy::Forget)
+        );
+        assert_eq!(DropPolicy::from_env_value("LOG"), Some(DropPolicy::Log));
+        assert_eq!(DropPolicy::from_env_value("other"), None);
+    }
+
+    #[test]
+    #[cfg(not(feature = "abort-only"))]
+    fn test_catch_unwind_with_policy() {
+        assert_eq!(
+            catch_unwind_with_policy(DropPolicy::Forget, || "success"),
+            Some("success")
+        );
+        assert_eq!(
+            catch_unwind_with_policy(DropPolicy::Log, endless_panic),
+            None
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "abort-only"))]
+    fn test_catch_unwind_with_policies() {
+        assert_eq!(
+            catch_unwind_with_policies(CatchPolicy::Log, DropPolicy::Abort, || "success"),
+            Some("success")
+        );
+        assert_eq!(
+            catch_unwind_with_policies(CatchPolicy::Log, DropPolicy::Forget, endless_panic),
+            None
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "abort-only"))]
     fn test_catch_unwind_wrapped() {
         assert!(matches!(catch_unwind_wrapped(|| "success"), Ok("success")));
 
@@ -177,4 +1477,162 @@ mod tests {
             Err(err) => drop_or_forget(err),
         }
     }
+
+    #[test]
+    #[cfg(not(feature = "abort-only"))]
+    fn test_drop_or_else_checked() {
+        assert!(drop_or_else_checked(String::new(), |_| ()).is_ok());
+        let err = drop_or_else_checked(Bomb, |payload| {
+            let is_bomb = payload.is::<Bomb>();
+            mem::forget(payload);
+            is_bomb
+        });
+        assert_eq!(err, Err(true));
+    }
+
+    #[test]
+    #[cfg(not(feature = "abort-only"))]
+    fn test_catch_unwind_partial() {
+        let mut acc = String::new();
+        assert!(matches!(
+            catch_unwind_partial(&mut acc, |acc| acc.push('a')),
+            Ok(())
+        ));
+        catch_unwind_partial(&mut acc, |acc| {
+            acc.push('b');
+            endless_panic();
+        })
+        .unwrap_err()
+        .drop_or_forget();
+        assert_eq!(acc, "ab");
+    }
+
+    #[test]
+    #[cfg(not(feature = "abort-only"))]
+    fn test_with_checkpoint() {
+        let mut state = String::from("a");
+        let result = with_checkpoint(&mut state, String::clone, |state| {
+            state.push('b');
+            state.len()
+        });
+        assert_eq!(result.ok(), Some(2));
+        assert_eq!(state, "ab");
+
+        with_checkpoint(&mut state, String::clone, |state| {
+            state.clear();
+            endless_panic()
+        })
+        .unwrap_err()
+        .drop_or_forget();
+        assert_eq!(state, "ab");
+    }
+
+    #[test]
+    #[cfg(not(feature = "abort-only"))]
+    fn test_catch_unwind_fn() {
+        assert_eq!(catch_unwind_fn(|| 1).ok(), Some(1));
+        catch_unwind_fn(endless_panic).unwrap_err().drop_or_forget();
+
+        fn fail(data: *mut c_void) {
+            panic!("{}", data.is_null())
+        }
+        let payload = catch_unwind_fn_raw(fail, core::ptr::null_mut()).unwrap_err();
+        assert_eq!(payload.message(), Some("true"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "abort-only"))]
+    fn test_catch_unwind_boxed() {
+        assert_eq!(catch_unwind_boxed(Box::new(|| 1)).ok(), Some(1));
+        catch_unwind_boxed(Box::new(endless_panic))
+            .unwrap_err()
+            .drop_or_forget();
+
+        let mut calls = 0;
+        let mut f = || {
+            calls += 1;
+            if calls == 1 {
+                panic!("test")
+            }
+            calls
+        };
+        assert_eq!(
+            catch_unwind_dyn_mut(&mut f).unwrap_err().message(),
+            Some("test")
+        );
+        assert_eq!(catch_unwind_dyn_mut(&mut f).ok(), Some(2));
+    }
+
+    #[test]
+    #[cfg(not(feature = "abort-only"))]
+    fn test_catch_unwind_pair() {
+        let (a, b) = catch_unwind_pair(|| 1, || "two");
+        assert!(matches!((a, b), (Ok(1), Ok("two"))));
+
+        let (a, b) = catch_unwind_pair(endless_panic, || panic!("second"));
+        a.unwrap_err().drop_or_forget();
+        assert_eq!(b.unwrap_err().message(), Some("second"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "abort-only"))]
+    fn test_payload_kind() {
+        let payload = catch_unwind_wrapped(|| panic!("test")).unwrap_err();
+        assert_eq!(payload.kind(), PayloadKind::StaticStr("test"));
+        let payload = catch_unwind_wrapped(|| panic!("{}", 1)).unwrap_err();
+        assert_eq!(payload.kind(), PayloadKind::String("1"));
+        assert_eq!(payload.kind().message(), Some("1"));
+        let payload = catch_unwind_wrapped(|| panic_any(1_u8)).unwrap_err();
+        assert_eq!(
+            payload.kind(),
+            PayloadKind::Other {
+                type_id: TypeId::of::<u8>()
+            }
+        );
+        assert_eq!(payload.kind().message(), None);
+    }
+
+    #[test]
+    #[cfg(not(feature = "abort-only"))]
+    fn test_drop_checked() {
+        assert!(catch_unwind_wrapped(|| panic!("test"))
+            .unwrap_err()
+            .drop_checked()
+            .is_ok());
+
+        let payload = catch_unwind_wrapped(endless_panic).unwrap_err();
+        let summary = payload.summary();
+        assert_eq!(summary.message(), None);
+        let double = payload.drop_checked().unwrap_err();
+        assert_eq!(double.original(), &summary);
+        assert!(double.to_string().ends_with("original panic: Box<dyn Any>"));
+        double.into_secondary().drop_or_forget();
+    }
+
+    #[test]
+    #[cfg(all(feature = "std", not(feature = "abort-only")))]
+    fn test_drop_or_exit() {
+        if in_child() {
+            drop_or_exit(String::from("success"), 3);
+            catch_unwind_wrapped(endless_panic)
+                .unwrap_err()
+                .drop_or_exit(3);
+            return;
+        }
+        let output = run_in_child("tests::test_drop_or_exit");
+        assert_eq!(output.status.code(), Some(3));
+        assert!(!String::from_utf8_lossy(&output.stdout).contains("test result"));
+    }
+
+    #[test]
+    #[cfg(all(feature = "std", feature = "abort-only"))]
+    fn test_abort_only() {
+        if in_child() {
+            let _ = catch_unwind_or_abort(|| panic!("test"));
+            return;
+        }
+        assert_eq!(catch_unwind_or_abort(|| 1), Some(1));
+        drop_or_abort(String::from("success"));
+        assert_aborted(&run_in_child("tests::test_abort_only"));
+    }
 }
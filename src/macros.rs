@@ -0,0 +1,415 @@
+//! Declarative macros
+
+use crate::{payload_message, CaughtPanic, Payload};
+use std::any::Any;
+
+/// Run a block of statements and catch any unwinding panics that may occur, like
+/// [`catch_unwind_wrapped`](crate::catch_unwind_wrapped) with the block wrapped in
+/// [`AssertUnwindSafe`](std::panic::AssertUnwindSafe).
+///
+/// The block is the body of a closure, so `return` and `?` exit the block rather than the
+/// enclosing function, and their value is the `Ok` value of the result.
+///
+/// ```
+/// use catch_unwind::{catch, Payload};
+/// use std::num::ParseIntError;
+///
+/// let result: Result<Result<i32, ParseIntError>, Payload> = catch! {
+///     let n: i32 = "21".parse()?;
+///     if n < 0 {
+///         panic!("negative");
+///     }
+///     Ok(n * 2)
+/// };
+/// assert!(matches!(result, Ok(Ok(42))));
+/// ```
+#[macro_export]
+macro_rules! catch {
+    ($($body:tt)*) => {
+        $crate::catch_unwind_wrapped(::core::panic::AssertUnwindSafe(|| { $($body)* }))
+    };
+}
+
+/// Assert that an expression panics, optionally with a message containing an expected
+/// substring.
+///
+/// Unlike `#[should_panic]`, this can be used in the middle of a test, and the panic is caught
+/// with [`catch_unwind_wrapped`](crate::catch_unwind_wrapped), so a payload that panics when
+/// dropped is forgotten instead of bringing down the test.
+///
+/// ```
+/// use catch_unwind::assert_panics;
+///
+/// assert_panics!(panic!("oh no"));
+/// assert_panics!(Option::<()>::None.unwrap(), "`None` value");
+/// ```
+#[macro_export]
+macro_rules! assert_panics {
+    ($expr:expr $(,)?) => {
+        $crate::__assert_panicked(
+            $crate::catch_unwind_wrapped(::core::panic::AssertUnwindSafe(|| $expr)),
+            ::core::stringify!($expr),
+            ::core::option::Option::None,
+        )
+    };
+    ($expr:expr, $expected:expr $(,)?) => {
+        $crate::__assert_panicked(
+            $crate::catch_unwind_wrapped(::core::panic::AssertUnwindSafe(|| $expr)),
+            ::core::stringify!($expr),
+            ::core::option::Option::Some($expected),
+        )
+    };
+}
+
+#[doc(hidden)]
+#[track_caller]
+pub fn __assert_panicked<T>(result: Result<T, Payload>, expr: &str, expected: Option<&str>) {
+    let payload = match result {
+        Ok(_) => panic!("assertion failed: `{expr}` didn't panic"),
+        Err(payload) => payload,
+    };
+    let message = payload.message().map(String::from);
+    payload.drop_or_forget();
+    if let Some(expected) = expected {
+        match message {
+            Some(message) if message.contains(expected) => (),
+            Some(message) => panic!(
+                "assertion failed: `{expr}` panicked with {message:?}, expected a message containing {expected:?}"
+            ),
+            None => panic!(
+                "assertion failed: `{expr}` panicked without a message, expected a message containing {expected:?}"
+            ),
+        }
+    }
+}
+
+/// Assert that an expression doesn't panic, and evaluate to its value.
+///
+/// The expression is run under
+/// [`catch_unwind_captured`](crate::catch_unwind_captured), and if it panics, the assertion
+/// fails with the message and location of the panic, instead of the panic propagating through
+/// the test harness.
+///
+/// ```
+/// use catch_unwind::assert_no_panic;
+///
+/// let value = assert_no_panic!("42".parse::<i32>().unwrap());
+/// assert_eq!(value, 42);
+/// ```
+#[macro_export]
+macro_rules! assert_no_panic {
+    ($expr:expr $(,)?) => {
+        $crate::__assert_no_panic(
+            $crate::catch_unwind_captured(::core::panic::AssertUnwindSafe(|| $expr)),
+            ::core::stringify!($expr),
+        )
+    };
+}
+
+#[doc(hidden)]
+#[track_caller]
+pub fn __assert_no_panic<T>(result: Result<T, CaughtPanic>, expr: &str) -> T {
+    let panic = match result {
+        Ok(ok) => return ok,
+        Err(panic) => panic,
+    };
+    let message = crate::panic_message!(panic).to_owned();
+    let location = panic.location().map(ToString::to_string);
+    panic.into_payload().drop_or_forget();
+    match location {
+        Some(location) => {
+            panic!("assertion failed: `{expr}` panicked at {location}:\n{message}")
+        }
+        None => panic!("assertion failed: `{expr}` panicked:\n{message}"),
+    }
+}
+
+/// Get a displayable message from a panic payload. The payload can be a [`Payload`],
+/// [`CaughtPanic`], `Box<dyn Any + Send>` or `&dyn Any`, and the message is `Box<dyn Any>` like
+/// in the standard library's panic hook if the payload isn't a string.
+///
+/// ```
+/// use catch_unwind::{catch_unwind_wrapped, panic_message};
+/// use std::panic::{catch_unwind, panic_any};
+///
+/// let payload = catch_unwind_wrapped(|| panic!("oh no")).unwrap_err();
+/// assert_eq!(panic_message!(payload), "oh no");
+///
+/// let payload = catch_unwind(|| panic_any(42)).unwrap_err();
+/// assert_eq!(panic_message!(payload), "Box<dyn Any>");
+/// ```
+#[macro_export]
+macro_rules! panic_message {
+    ($payload:expr $(,)?) => {
+        $crate::__PanicMessage::__panic_message(&$payload)
+    };
+}
+
+/// Match on the type of a [`Payload`], taking the value out for the first arm whose type it
+/// has.
+///
+/// An arm is written as `binding: Type => expression`, and the binding gets the payload as an
+/// owned `Type`. The `&str` type is special, and matches both `&'static str` and `String`
+/// payloads, with the binding borrowing the message. The last arm is required, and is either
+/// `binding => expression`, which gets the unmatched [`Payload`], or `_ => expression`, which
+/// drops it according to the current thread's default policy. Arms are separated by commas,
+/// even when the expression is a block.
+///
+/// ```
+/// use catch_unwind::{catch_unwind_wrapped, match_payload};
+/// use std::panic::panic_any;
+///
+/// #[derive(Debug)]
+/// struct MyError(i32);
+///
+/// let describe = |f: fn()| {
+///     let payload = catch_unwind_wrapped(f).unwrap_err();
+///     match_payload!(payload, {
+///         msg: &str => format!("message: {msg}"),
+///         err: MyError => format!("error: {}", err.0),
+///         other => {
+///             other.drop_or_forget();
+///             "other".to_string()
+///         },
+///     })
+/// };
+/// assert_eq!(describe(|| panic!("oh no")), "message: oh no");
+/// assert_eq!(describe(|| panic_any(MyError(1))), "error: 1");
+/// assert_eq!(describe(|| panic_any(42)), "other");
+/// ```
+#[macro_export]
+macro_rules! match_payload {
+    ($payload:expr, { $($arms:tt)* }) => {{
+        let payload: $crate::Payload = $payload;
+        $crate::__match_payload!(payload; $($arms)*)
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __match_payload {
+    ($payload:ident; _ => $body:expr $(,)?) => {{
+        ::core::mem::drop($payload);
+        $body
+    }};
+    ($payload:ident; $other:ident => $body:expr $(,)?) => {{
+        let $other: $crate::Payload = $payload;
+        $body
+    }};
+    ($payload:ident; $bind:ident : &str => $body:expr, $($rest:tt)*) => {
+        match $payload.message() {
+            ::core::option::Option::Some($bind) => $body,
+            ::core::option::Option::None => $crate::__match_payload!($payload; $($rest)*),
+        }
+    };
+    ($payload:ident; $bind:ident : $ty:ty => $body:expr, $($rest:tt)*) => {
+        match $payload.downcast::<$ty>() {
+            ::core::result::Result::Ok($bind) => {
+                let $bind: $ty = *$bind;
+                $body
+            }
+            ::core::result::Result::Err($payload) => {
+                $crate::__match_payload!($payload; $($rest)*)
+            }
+        }
+    };
+}
+
+/// Drop a value, and return early from the enclosing function if dropping it panics, like `?`.
+/// The panic payload is converted to the function's error type with `From<Payload>`.
+///
+/// ```
+/// use catch_unwind::{try_drop, Payload};
+///
+/// struct Bomb;
+///
+/// impl Drop for Bomb {
+///     fn drop(&mut self) {
+///         panic!("boom");
+///     }
+/// }
+///
+/// fn cleanup(value: Bomb) -> Result<(), Payload> {
+///     try_drop!(value);
+///     Ok(())
+/// }
+///
+/// let payload = cleanup(Bomb).unwrap_err();
+/// assert_eq!(payload.message(), Some("boom"));
+/// ```
+#[macro_export]
+macro_rules! try_drop {
+    ($value:expr $(,)?) => {
+        if let ::core::result::Result::Err(payload) = $crate::drop_wrapped($value) {
+            return ::core::result::Result::Err(::core::convert::From::from(payload));
+        }
+    };
+}
+
+#[doc(hidden)]
+pub trait __PanicMessage {
+    fn __panic_message(&self) -> &str;
+}
+
+impl __PanicMessage for dyn Any {
+    #[inline]
+    fn __panic_message(&self) -> &str {
+        payload_message(self).unwrap_or("Box<dyn Any>")
+    }
+}
+
+impl __PanicMessage for dyn Any + Send {
+    #[inline]
+    fn __panic_message(&self) -> &str {
+        payload_message(self).unwrap_or("Box<dyn Any>")
+    }
+}
+
+impl __PanicMessage for Payload {
+    #[inline]
+    fn __panic_message(&self) -> &str {
+        self.get().__panic_message()
+    }
+}
+
+impl __PanicMessage for CaughtPanic {
+    #[inline]
+    fn __panic_message(&self) -> &str {
+        self.payload().__panic_message()
+    }
+}
+
+impl<T: __PanicMessage + ?Sized> __PanicMessage for Box<T> {
+    #[inline]
+    fn __panic_message(&self) -> &str {
+        (**self).__panic_message()
+    }
+}
+
+impl<T: __PanicMessage + ?Sized> __PanicMessage for &T {
+    #[inline]
+    fn __panic_message(&self) -> &str {
+        (**self).__panic_message()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_catch() {
+        let mut value = 0;
+        let result = catch! {
+            value += 1;
+            if value > 0 {
+                return value;
+            }
+            unreachable!()
+        };
+        assert!(matches!(result, Ok(1)));
+
+        let result: Result<(), _> = catch! { panic!("test") };
+        assert_eq!(result.unwrap_err().message(), Some("test"));
+    }
+
+    #[test]
+    fn test_assert_panics() {
+        assert_panics!(crate::tests::endless_panic());
+        assert_panics!(panic!("oh no"), "no");
+        assert_panics!(assert_panics!(()));
+        assert_panics!(
+            assert_panics!(panic!("test"), "other"),
+            "expected a message"
+        );
+    }
+
+    #[test]
+    fn test_match_payload() {
+        let classify = |payload| {
+            match_payload!(payload, {
+                s: &str => s.len(),
+                n: u8 => n.into(),
+                _ => 0,
+            })
+        };
+        let payload = crate::catch_unwind_wrapped(|| std::panic::panic_any(7_u8)).unwrap_err();
+        assert_eq!(classify(payload), 7);
+        let payload = crate::catch_unwind_wrapped(|| panic!("{}", "test")).unwrap_err();
+        assert_eq!(classify(payload), 4);
+
+        let payload = crate::catch_unwind_wrapped(crate::tests::endless_panic).unwrap_err();
+        match_payload!(payload, {
+            s: String => unreachable!("{s}"),
+            other => other.drop_or_forget(),
+        });
+    }
+
+    #[test]
+    fn test_assert_no_panic() {
+        assert_eq!(assert_no_panic!(1 + 1), 2);
+        assert_panics!(assert_no_panic!(panic!("test")), "macros.rs");
+    }
+
+    #[test]
+    fn test_try_drop() {
+        use crate::tests::Bomb;
+
+        fn cleanup(a: String, b: Bomb) -> Result<(), crate::Payload> {
+            try_drop!(a);
+            try_drop!(b);
+            Ok(())
+        }
+
+        cleanup(String::new(), Bomb).unwrap_err().drop_or_forget();
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let payload = std::panic::catch_unwind(|| panic!("{}", 1)).unwrap_err();
+        assert_eq!(panic_message!(payload), "1");
+        assert_eq!(panic_message!(&*payload), "1");
+        let payload: &dyn std::any::Any = &1;
+        assert_eq!(panic_message!(payload), "Box<dyn Any>");
+        let panic = crate::catch_unwind_captured(|| panic!("test")).unwrap_err();
+        assert_eq!(panic_message!(panic), "test");
+        assert_eq!(panic_message!(panic.payload()), "test");
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn test_catch_unwind_attribute() {
+        #[derive(Debug)]
+        enum Error {
+            Negative,
+            Panic(Option<String>),
+        }
+
+        impl From<crate::Payload> for Error {
+            fn from(payload: crate::Payload) -> Self {
+                let message = payload.message().map(String::from);
+                payload.drop_or_forget();
+                Self::Panic(message)
+            }
+        }
+
+        #[crate::catch_unwind]
+        fn checked_div(a: i32, b: i32) -> Result<i32, Error> {
+            if a < 0 {
+                return Err(Error::Negative);
+            }
+            Ok(a / b)
+        }
+
+        #[crate::catch_unwind(crate = "crate")]
+        fn fail() -> Result<(), Error> {
+            panic!("test")
+        }
+
+        assert!(matches!(checked_div(6, 3), Ok(2)));
+        assert!(matches!(checked_div(-6, 3), Err(Error::Negative)));
+        assert!(matches!(
+            checked_div(6, 0),
+            Err(Error::Panic(Some(message))) if message.contains("divide by zero")
+        ));
+        assert!(matches!(fail(), Err(Error::Panic(Some(message))) if message == "test"));
+    }
+}
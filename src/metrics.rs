@@ -0,0 +1,130 @@
+//! Emitting counters for caught panics through the [`metrics`](::metrics) facade
+//!
+//! The following counters are incremented by this crate:
+//!
+//! - [`PANICS_CAUGHT`]: panics caught by this crate, other than from dropping values
+//! - [`DROP_PANICS`]: panics from dropping payloads or other values
+//! - [`ABORTS_AVOIDED`]: panics from dropping a value that were handled without aborting
+//! - [`PAYLOADS_FORGOTTEN`]: payloads that were forgotten because they panicked when dropped
+//!
+//! Counters incremented inside [`with_catch_site`] have a `site` label with the name of the
+//! catch site.
+
+use ::metrics::counter;
+use std::cell::Cell;
+
+/// Name of the counter of caught panics
+pub const PANICS_CAUGHT: &str = "catch_unwind_panics_caught_total";
+
+/// Name of the counter of panics from dropping values
+pub const DROP_PANICS: &str = "catch_unwind_drop_panics_total";
+
+/// Name of the counter of panics from dropping values that were handled without aborting
+pub const ABORTS_AVOIDED: &str = "catch_unwind_aborts_avoided_total";
+
+/// Name of the counter of forgotten payloads
+pub const PAYLOADS_FORGOTTEN: &str = "catch_unwind_payloads_forgotten_total";
+
+thread_local! {
+    static CATCH_SITE: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// Invoke the provided closure with `site` as the `site` label of the counters incremented on the
+/// current thread. The previous catch site is restored afterwards, even if the closure panics.
+pub fn with_catch_site<F: FnOnce() -> R, R>(site: &'static str, f: F) -> R {
+    struct Restore(Option<&'static str>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CATCH_SITE.with(|site| site.set(self.0));
+        }
+    }
+
+    let _restore = Restore(CATCH_SITE.with(|prev| prev.replace(Some(site))));
+    f()
+}
+
+fn increment(name: &'static str) {
+    match CATCH_SITE.try_with(Cell::get).ok().flatten() {
+        Some(site) => counter!(name, "site" => site).increment(1),
+        None => counter!(name).increment(1),
+    }
+}
+
+#[inline]
+pub(crate) fn panic_caught() {
+    increment(PANICS_CAUGHT)
+}
+
+#[inline]
+pub(crate) fn drop_panicked() {
+    increment(DROP_PANICS)
+}
+
+#[inline]
+pub(crate) fn abort_avoided() {
+    increment(ABORTS_AVOIDED)
+}
+
+#[inline]
+pub(crate) fn payload_forgotten() {
+    increment(PAYLOADS_FORGOTTEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::metrics::{
+        with_local_recorder, Counter, Gauge, Histogram, Key, KeyName, Metadata, Recorder,
+        SharedString, Unit,
+    };
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct TestRecorder(Mutex<Vec<String>>);
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            let mut name = key.name().to_owned();
+            for label in key.labels() {
+                name = format!("{name} {}={}", label.key(), label.value());
+            }
+            self.0.lock().unwrap().push(name);
+            Counter::noop()
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    #[test]
+    fn test_counters() {
+        let recorder = TestRecorder::default();
+        with_local_recorder(&recorder, || {
+            with_catch_site("outer", || {
+                let _ =
+                    crate::catch_unwind_or_forget(|| with_catch_site("inner", || panic!("test")));
+                let _ = crate::catch_unwind_or_forget(crate::tests::endless_panic);
+            })
+        });
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [
+                "catch_unwind_panics_caught_total site=outer",
+                "catch_unwind_panics_caught_total site=outer",
+                "catch_unwind_drop_panics_total site=outer",
+                "catch_unwind_payloads_forgotten_total site=outer",
+                "catch_unwind_aborts_avoided_total site=outer",
+            ]
+        );
+    }
+}
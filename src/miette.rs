@@ -0,0 +1,138 @@
+//! Rendering caught panics as [`miette`](::miette) diagnostics
+
+use crate::{panic_message, thread::default_policy, CaughtPanic, PanicLocation, Payload};
+use ::miette::{Diagnostic, LabeledSpan, NamedSource, Report, SourceCode, SourceOffset};
+use std::{error::Error, fmt, fs};
+
+/// Diagnostic for a caught panic, with the location of the panic as a labeled source span if it
+/// was captured and its source file can be read.
+#[derive(Debug)]
+pub struct PanicDiagnostic {
+    message: String,
+    location: Option<PanicLocation>,
+    source: Option<(NamedSource<String>, SourceOffset)>,
+}
+
+impl PanicDiagnostic {
+    /// Get the panic message. This is `Box<dyn Any>` if the payload wasn't a string.
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Get the location the panic originated from, if it was captured
+    #[inline]
+    pub fn location(&self) -> Option<&PanicLocation> {
+        self.location.as_ref()
+    }
+}
+
+/// Create a diagnostic with the panic message. The payload is dropped according to the current
+/// thread's default policy.
+impl From<Payload> for PanicDiagnostic {
+    fn from(payload: Payload) -> Self {
+        let message = panic_message!(payload).to_owned();
+        payload.drop_with_policy(default_policy());
+        Self {
+            message,
+            location: None,
+            source: None,
+        }
+    }
+}
+
+/// Create a diagnostic with the panic message and location. The source file of the location is
+/// read for the source span, relative to the current directory. The payload is dropped
+/// according to the current thread's default policy.
+impl From<CaughtPanic> for PanicDiagnostic {
+    fn from(panic: CaughtPanic) -> Self {
+        let message = panic_message!(panic).to_owned();
+        let location = panic.location().cloned();
+        panic.into_payload().drop_with_policy(default_policy());
+        let source = location.as_ref().and_then(|location| {
+            let source = fs::read_to_string(location.file()).ok()?;
+            let offset = SourceOffset::from_location(
+                &source,
+                location.line() as usize,
+                location.column() as usize,
+            );
+            Some((NamedSource::new(location.file(), source), offset))
+        });
+        Self {
+            message,
+            location,
+            source,
+        }
+    }
+}
+
+/// Convert a caught panic into a report via [`PanicDiagnostic`]
+impl From<CaughtPanic> for Report {
+    #[inline]
+    fn from(panic: CaughtPanic) -> Self {
+        Report::new(PanicDiagnostic::from(panic))
+    }
+}
+
+impl fmt::Display for PanicDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "panicked at {location}: {}", self.message),
+            None => write!(f, "panicked: {}", self.message),
+        }
+    }
+}
+
+impl Error for PanicDiagnostic {}
+
+impl Diagnostic for PanicDiagnostic {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new("catch_unwind::panic"))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(
+            "this is a bug in the code that panicked; the panic was caught instead of unwinding",
+        ))
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        self.source
+            .as_ref()
+            .map(|(source, _)| source as &dyn SourceCode)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let (_, offset) = self.source.as_ref()?;
+        Some(Box::new(std::iter::once(LabeledSpan::at_offset(
+            offset.offset(),
+            self.message.clone(),
+        ))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catch_unwind_captured;
+
+    #[test]
+    fn test_panic_diagnostic() {
+        let line = line!() + 1;
+        let panic = catch_unwind_captured(|| panic!("test")).unwrap_err();
+        let diagnostic = PanicDiagnostic::from(panic);
+        assert_eq!(diagnostic.message(), "test");
+        assert_eq!(diagnostic.location().unwrap().line(), line);
+        assert_eq!(
+            diagnostic.code().unwrap().to_string(),
+            "catch_unwind::panic"
+        );
+        let label = diagnostic.labels().unwrap().next().unwrap();
+        let contents = diagnostic
+            .source_code()
+            .unwrap()
+            .read_span(label.inner(), 0, 0)
+            .unwrap();
+        assert_eq!(contents.line() + 1, line as usize);
+    }
+}
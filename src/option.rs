@@ -0,0 +1,101 @@
+//! Dropping optional values under panic protection
+
+use crate::{drop_or_abort, drop_or_forget, drop_or_log, drop_with_policy, DropPolicy};
+
+/// Extension trait for dropping the value of an [`Option`] whose destructor may panic, e.g. a
+/// field dropped in a `Drop` implementation
+///
+/// ```
+/// use catch_unwind::option::OptionExt;
+///
+/// struct Handle;
+///
+/// impl Drop for Handle {
+///     fn drop(&mut self) {
+///         panic!("oh no");
+///     }
+/// }
+///
+/// struct Connection {
+///     handle: Option<Handle>,
+/// }
+///
+/// impl Drop for Connection {
+///     fn drop(&mut self) {
+///         self.handle.take_and_drop_or_forget();
+///     }
+/// }
+///
+/// drop(Connection {
+///     handle: Some(Handle),
+/// });
+/// ```
+pub trait OptionExt {
+    /// Take the value out of the option, leaving `None`, and drop it. If dropping it panics,
+    /// abort the process. See [`drop_or_abort`].
+    fn take_and_drop_or_abort(&mut self);
+
+    /// Take the value out of the option, leaving `None`, and drop it. If dropping it panics,
+    /// `mem::forget` the new panic payload. See [`drop_or_forget`].
+    fn take_and_drop_or_forget(&mut self);
+
+    /// Take the value out of the option, leaving `None`, and drop it. If dropping it panics,
+    /// write a message to stderr and `mem::forget` the new panic payload. See [`drop_or_log`].
+    fn take_and_drop_or_log(&mut self);
+
+    /// Take the value out of the option, leaving `None`, and drop it, handling a panic on drop
+    /// according to `policy`. See [`drop_with_policy`].
+    fn take_and_drop_with_policy(&mut self, policy: DropPolicy);
+}
+
+impl<T> OptionExt for Option<T> {
+    #[inline]
+    fn take_and_drop_or_abort(&mut self) {
+        if let Some(value) = self.take() {
+            drop_or_abort(value)
+        }
+    }
+
+    #[inline]
+    fn take_and_drop_or_forget(&mut self) {
+        if let Some(value) = self.take() {
+            drop_or_forget(value)
+        }
+    }
+
+    #[inline]
+    fn take_and_drop_or_log(&mut self) {
+        if let Some(value) = self.take() {
+            drop_or_log(value)
+        }
+    }
+
+    #[inline]
+    fn take_and_drop_with_policy(&mut self, policy: DropPolicy) {
+        if let Some(value) = self.take() {
+            drop_with_policy(value, policy)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::Bomb;
+
+    #[test]
+    fn test_take_and_drop() {
+        let mut value = Some(Bomb);
+        value.take_and_drop_or_forget();
+        assert!(value.is_none());
+        value.take_and_drop_or_abort();
+
+        let mut value = Some(Bomb);
+        value.take_and_drop_or_log();
+        assert!(value.is_none());
+
+        let mut value = Some(Bomb);
+        value.take_and_drop_with_policy(DropPolicy::Forget);
+        assert!(value.is_none());
+    }
+}
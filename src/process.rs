@@ -0,0 +1,213 @@
+//! Panic boundary for the top of `main`
+
+use crate::{
+    catch_unwind_with_policy, catch_unwind_wrapped, report::report_guarded, thread::default_policy,
+    DropPolicy, PanicReporter, Payload, StderrReporter,
+};
+use std::{
+    any::Any,
+    panic::AssertUnwindSafe,
+    process::{ExitCode, Termination},
+    sync::Arc,
+};
+
+/// Exit code used for caught panics by default. This is the same as the exit code the standard
+/// library uses when `main` panics.
+pub const DEFAULT_PANIC_EXIT_CODE: u8 = 101;
+
+type ExitCodeMatcher = Box<dyn Fn(&Payload) -> bool>;
+
+/// Configurable panic boundary for `main`. See [`run_main`].
+///
+/// The exit code for a caught panic can depend on the payload, so that orchestration systems
+/// can tell different kinds of panics apart by the process status:
+///
+/// ```
+/// use catch_unwind::process::Main;
+/// use std::{panic::panic_any, process::ExitCode};
+///
+/// struct OutOfResources;
+///
+/// let main = || {
+///     Main::new()
+///         .no_reporter()
+///         .exit_code_for::<OutOfResources>(3)
+///         .exit_code_for_message(|msg| msg.starts_with("assertion"), 4)
+/// };
+/// assert_eq!(main().run(|| panic_any(OutOfResources)), ExitCode::from(3));
+/// assert_eq!(main().run(|| assert!(1 + 1 == 3)), ExitCode::from(4));
+/// assert_eq!(main().run(|| panic!("oh no")), ExitCode::from(101));
+/// ```
+pub struct Main {
+    panic_exit_code: u8,
+    exit_codes: Vec<(ExitCodeMatcher, u8)>,
+    policy: Option<DropPolicy>,
+    reporter: Option<Arc<dyn PanicReporter>>,
+}
+
+impl Default for Main {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Main {
+    /// Create a new panic boundary with the default configuration. This uses
+    /// [`DEFAULT_PANIC_EXIT_CODE`], the current thread's default policy, and [`StderrReporter`].
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            panic_exit_code: DEFAULT_PANIC_EXIT_CODE,
+            exit_codes: Vec::new(),
+            policy: None,
+            reporter: Some(Arc::new(StderrReporter)),
+        }
+    }
+
+    /// Set the exit code to use if a panic is caught and none of the exit codes for specific
+    /// panics apply
+    #[inline]
+    pub fn panic_exit_code(mut self, code: u8) -> Self {
+        self.panic_exit_code = code;
+        self
+    }
+
+    /// Use `code` as the exit code if the payload of a caught panic has type `T`, e.g. one raised
+    /// with [`std::panic::panic_any`].
+    ///
+    /// The exit codes for specific panics are checked in the order they were added, and the
+    /// first one that applies is used.
+    #[inline]
+    pub fn exit_code_for<T: Any>(self, code: u8) -> Self {
+        self.exit_code_if(|payload| payload.get().is::<T>(), code)
+    }
+
+    /// Use `code` as the exit code if a caught panic has a message for which `predicate` returns
+    /// `true`. See [`exit_code_for`](Self::exit_code_for).
+    #[inline]
+    pub fn exit_code_for_message(
+        self,
+        predicate: impl Fn(&str) -> bool + 'static,
+        code: u8,
+    ) -> Self {
+        self.exit_code_if(
+            move |payload| payload.message().is_some_and(&predicate),
+            code,
+        )
+    }
+
+    /// Use `code` as the exit code if `predicate` returns `true` for the payload of a caught
+    /// panic. A predicate that panics doesn't apply. See [`exit_code_for`](Self::exit_code_for).
+    #[inline]
+    pub fn exit_code_if(
+        mut self,
+        predicate: impl Fn(&Payload) -> bool + 'static,
+        code: u8,
+    ) -> Self {
+        self.exit_codes.push((Box::new(predicate), code));
+        self
+    }
+
+    /// Set the policy for handling a panic payload that panics when dropped
+    #[inline]
+    pub fn policy(mut self, policy: DropPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Set the reporter that a caught panic is sent to
+    #[inline]
+    pub fn reporter(mut self, reporter: impl PanicReporter + 'static) -> Self {
+        self.reporter = Some(Arc::new(reporter));
+        self
+    }
+
+    /// Don't report a caught panic
+    #[inline]
+    pub fn no_reporter(mut self) -> Self {
+        self.reporter = None;
+        self
+    }
+
+    /// Invoke the provided closure and catch any unwinding panics that may occur, returning an
+    /// exit code for the process.
+    ///
+    /// If no panic was caught, the result of the closure is converted to an exit code with
+    /// [`Termination::report`]. Otherwise the panic is reported, the payload is dropped according
+    /// to the configured policy, and the configured exit code for the panic is returned.
+    pub fn run<F: FnOnce() -> R, R: Termination>(self, f: F) -> ExitCode {
+        // `f` is the entire program, so nothing can observe broken invariants after a panic
+        match catch_unwind_wrapped(AssertUnwindSafe(|| f().report())) {
+            Ok(code) => code,
+            Err(payload) => {
+                let policy = self.policy.unwrap_or_else(default_policy);
+                if let Some(reporter) = &self.reporter {
+                    report_guarded(&**reporter, &payload, policy);
+                }
+                let code = self.exit_code(&payload, policy);
+                payload.drop_with_policy(policy);
+                ExitCode::from(code)
+            }
+        }
+    }
+
+    fn exit_code(&self, payload: &Payload, policy: DropPolicy) -> u8 {
+        self.exit_codes
+            .iter()
+            .find(|(predicate, _)| {
+                catch_unwind_with_policy(policy, AssertUnwindSafe(|| predicate(payload)))
+                    .unwrap_or(false)
+            })
+            .map_or(self.panic_exit_code, |&(_, code)| code)
+    }
+}
+
+/// Invoke the provided closure and catch any unwinding panics that may occur, returning an exit
+/// code for the process. This is intended to be used as the entire body of `main`:
+///
+/// ```no_run
+/// use std::process::ExitCode;
+///
+/// fn main() -> ExitCode {
+///     catch_unwind::process::run_main(|| {
+///         // ...
+///     })
+/// }
+/// ```
+///
+/// If a panic is caught, it's reported to stderr, the payload is dropped according to the
+/// current thread's default policy, and [`DEFAULT_PANIC_EXIT_CODE`] is returned. Use [`Main`]
+/// to configure this.
+#[inline]
+pub fn run_main<F: FnOnce() -> R, R: Termination>(f: F) -> ExitCode {
+    Main::new().run(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::endless_panic;
+
+    #[test]
+    fn test_run_main() {
+        assert_eq!(run_main(|| ()), ExitCode::SUCCESS);
+        assert_eq!(
+            Main::new()
+                .panic_exit_code(3)
+                .policy(DropPolicy::Forget)
+                .run(endless_panic),
+            ExitCode::from(3)
+        );
+
+        let main = || {
+            Main::new()
+                .no_reporter()
+                .exit_code_if(|_| panic!("predicate"), 2)
+                .exit_code_for::<&str>(3)
+                .exit_code_for::<String>(4)
+        };
+        assert_eq!(main().run(|| panic!("test")), ExitCode::from(3));
+        assert_eq!(main().run(|| panic!("{}", 1)), ExitCode::from(4));
+    }
+}
@@ -0,0 +1,77 @@
+//! Converting caught panics into Python exceptions with [`pyo3`](::pyo3)
+
+use crate::{catch_unwind_captured, thread::default_policy, CaughtPanic, Payload};
+use ::pyo3::{panic::PanicException, PyErr, PyResult};
+use std::{backtrace::BacktraceStatus, fmt::Write, panic::UnwindSafe};
+
+const NON_STRING_MESSAGE: &str = "panic from Rust code";
+
+/// Convert a caught panic into a Python `PanicException` with the panic message. The payload is
+/// dropped according to the current thread's default policy.
+impl From<Payload> for PyErr {
+    fn from(payload: Payload) -> Self {
+        let msg = payload.message().unwrap_or(NON_STRING_MESSAGE).to_owned();
+        payload.drop_with_policy(default_policy());
+        PanicException::new_err((msg,))
+    }
+}
+
+/// Convert a caught panic into a Python `PanicException` with the panic message, followed by
+/// the location and backtrace of the panic if they were captured. The payload is dropped
+/// according to the current thread's default policy.
+impl From<CaughtPanic> for PyErr {
+    fn from(panic: CaughtPanic) -> Self {
+        let mut msg = panic.message().unwrap_or(NON_STRING_MESSAGE).to_owned();
+        if let Some(location) = panic.location() {
+            let _ = write!(msg, "\n  at {location}");
+        }
+        if let Some(backtrace) = panic.backtrace() {
+            if backtrace.status() == BacktraceStatus::Captured {
+                let _ = write!(msg, "\n\nstack backtrace:\n{backtrace}");
+            }
+        }
+        panic.into_payload().drop_with_policy(default_policy());
+        PanicException::new_err((msg,))
+    }
+}
+
+/// Invoke the provided closure and catch any unwinding panics that may occur, for use as the
+/// body of a `#[pyfunction]`. A caught panic is converted into a Python `PanicException` that
+/// includes the location and backtrace of the panic.
+///
+/// ```
+/// use catch_unwind::pyo3::catch_unwind_py;
+/// use pyo3::prelude::*;
+///
+/// #[pyfunction]
+/// fn half(value: u32) -> PyResult<u32> {
+///     catch_unwind_py(|| {
+///         assert!(value % 2 == 0, "value is odd");
+///         Ok(value / 2)
+///     })
+/// }
+/// ```
+#[inline]
+pub fn catch_unwind_py<F: FnOnce() -> PyResult<R> + UnwindSafe, R>(f: F) -> PyResult<R> {
+    catch_unwind_captured(f).unwrap_or_else(|panic| Err(panic.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::pyo3::Python;
+
+    #[test]
+    fn test_catch_unwind_py() {
+        assert_eq!(catch_unwind_py(|| Ok(1)).ok(), Some(1));
+
+        let err = catch_unwind_py(|| -> PyResult<()> { panic!("test") }).unwrap_err();
+        Python::initialize();
+        Python::attach(|py| {
+            assert!(err.is_instance_of::<PanicException>(py));
+            let msg = err.value(py).to_string();
+            assert!(msg.starts_with("test\n  at "));
+            assert!(msg.contains(file!()));
+        });
+    }
+}
@@ -0,0 +1,196 @@
+//! Guarded wrappers for [`rayon`](::rayon)'s `join` and `scope`, and per-item catching
+//! parallel iterator adapters
+//!
+//! Rayon propagates panics from its tasks as raw payloads, and drops any extra payloads inside
+//! the thread pool. These wrappers catch the panics in each task instead, so every payload is
+//! protected by [`Payload`].
+
+use crate::{catch_unwind_wrapped, MultiPayload, Payload};
+use ::rayon::iter::ParallelIterator;
+use std::{
+    iter, mem,
+    panic::AssertUnwindSafe,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+/// Run two closures, potentially in parallel, like [`rayon::join`](::rayon::join), and catch
+/// any unwinding panics that may occur in either of them.
+#[inline]
+pub fn join_caught<A, B, RA, RB>(a: A, b: B) -> (Result<RA, Payload>, Result<RB, Payload>)
+where
+    A: FnOnce() -> RA + Send,
+    B: FnOnce() -> RB + Send,
+    RA: Send,
+    RB: Send,
+{
+    ::rayon::join(
+        || catch_unwind_wrapped(AssertUnwindSafe(a)),
+        || catch_unwind_wrapped(AssertUnwindSafe(b)),
+    )
+}
+
+/// Scope for spawning guarded tasks. See [`scope`].
+pub struct Scope<'a, 'scope> {
+    inner: &'a ::rayon::Scope<'scope>,
+    panics: Arc<Mutex<MultiPayload>>,
+}
+
+impl<'scope> Scope<'_, 'scope> {
+    /// Spawn a task in the scope, like [`rayon::Scope::spawn`](::rayon::Scope::spawn). If the
+    /// task panics, the payload is collected and returned from [`scope`].
+    pub fn spawn<F>(&self, f: F)
+    where
+        F: FnOnce(&Scope<'_, 'scope>) + Send + 'scope,
+    {
+        let panics = Arc::clone(&self.panics);
+        self.inner.spawn(move |inner| {
+            let scope = Scope { inner, panics };
+            if let Err(payload) = catch_unwind_wrapped(AssertUnwindSafe(|| f(&scope))) {
+                lock(&scope.panics).push(payload);
+            }
+        })
+    }
+}
+
+/// Create a scope for spawning tasks, like [`rayon::scope`](::rayon::scope), and catch any
+/// unwinding panics that occur in `op` or in any of the spawned tasks.
+///
+/// Returns `Ok` if no panics were caught, and `Err` with all the caught payloads otherwise.
+pub fn scope<'scope, OP, R>(op: OP) -> Result<R, MultiPayload>
+where
+    OP: FnOnce(&Scope<'_, 'scope>) -> R + Send,
+    R: Send,
+{
+    let panics = Arc::new(Mutex::new(MultiPayload::new()));
+    let result = catch_unwind_wrapped(AssertUnwindSafe(|| {
+        ::rayon::scope(|inner| {
+            op(&Scope {
+                inner,
+                panics: Arc::clone(&panics),
+            })
+        })
+    }));
+    let panics = mem::take(&mut *lock(&panics));
+    match result {
+        Ok(ok) if panics.is_empty() => Ok(ok),
+        Ok(_) => Err(panics),
+        Err(payload) => Err(iter::once(payload).chain(panics).collect()),
+    }
+}
+
+/// Extension trait for catching panics per item in parallel iterators
+pub trait ParallelIteratorExt: ParallelIterator {
+    /// Map each item with the provided closure in parallel, and catch any unwinding panics
+    /// that may occur while mapping an item. A caught panic is yielded as an `Err(Payload)`
+    /// item, and the other items are unaffected.
+    ///
+    /// ```
+    /// use catch_unwind::rayon::ParallelIteratorExt;
+    /// use rayon::prelude::*;
+    ///
+    /// let results: Vec<_> = ["1", "x", "3"]
+    ///     .into_par_iter()
+    ///     .par_map_caught(|s| s.parse::<i32>().unwrap())
+    ///     .map(|result| result.map_err(|payload| payload.drop_or_forget()))
+    ///     .collect();
+    /// assert_eq!(results, [Ok(1), Err(()), Ok(3)]);
+    /// ```
+    ///
+    /// See [`std::panic::catch_unwind`] for more information.
+    #[inline]
+    fn par_map_caught<F, T>(self, f: F) -> impl ParallelIterator<Item = Result<T, Payload>>
+    where
+        F: Fn(Self::Item) -> T + Sync + Send,
+        T: Send,
+    {
+        self.map(move |item| catch_unwind_wrapped(AssertUnwindSafe(|| f(item))))
+    }
+
+    /// Call the provided closure for each item in parallel, and catch any unwinding panics that
+    /// may occur. A panic for one item doesn't stop the others from being processed.
+    ///
+    /// Returns `Ok` if no panics were caught, and `Err` with all the caught payloads otherwise.
+    ///
+    /// See [`std::panic::catch_unwind`] for more information.
+    fn par_for_each_caught<F>(self, f: F) -> Result<(), MultiPayload>
+    where
+        F: Fn(Self::Item) + Sync + Send,
+    {
+        let panics: Vec<_> = self
+            .filter_map(|item| catch_unwind_wrapped(AssertUnwindSafe(|| f(item))).err())
+            .collect();
+        if panics.is_empty() {
+            Ok(())
+        } else {
+            Err(panics.into())
+        }
+    }
+}
+
+impl<I: ParallelIterator> ParallelIteratorExt for I {}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::endless_panic;
+
+    #[test]
+    fn test_join_caught() {
+        let (a, b) = join_caught(|| "success", endless_panic);
+        assert!(matches!(a, Ok("success")));
+        b.unwrap_err().drop_or_forget();
+    }
+
+    #[test]
+    fn test_scope() {
+        assert!(matches!(scope(|_| "success"), Ok("success")));
+
+        let panics = scope(|s| {
+            s.spawn(|_| endless_panic());
+            s.spawn(|s| s.spawn(|_| endless_panic()));
+        })
+        .unwrap_err();
+        assert_eq!(panics.len(), 2);
+        panics.drop_or_forget();
+
+        let panics = scope(|s| {
+            s.spawn(|_| panic!("task"));
+            panic!("op")
+        })
+        .unwrap_err();
+        assert_eq!(panics.messages().collect::<Vec<_>>(), ["op", "task"]);
+    }
+
+    #[test]
+    fn test_par_caught() {
+        use ::rayon::iter::IntoParallelIterator;
+
+        let results: Vec<_> = (0..4)
+            .into_par_iter()
+            .par_map_caught(|i| {
+                if i % 2 == 1 {
+                    endless_panic()
+                }
+                i
+            })
+            .map(|item| item.map_err(Payload::drop_or_forget))
+            .collect();
+        assert_eq!(results, [Ok(0), Err(()), Ok(2), Err(())]);
+
+        assert!((0..4).into_par_iter().par_for_each_caught(|_| ()).is_ok());
+        let panics = (0..4)
+            .into_par_iter()
+            .par_for_each_caught(|i| {
+                if i != 0 {
+                    endless_panic()
+                }
+            })
+            .unwrap_err();
+        assert_eq!(panics.len(), 3);
+        panics.drop_or_forget();
+    }
+}
@@ -0,0 +1,231 @@
+//! Reporting of caught panics
+
+use crate::{catch_unwind_with_policy, panic_message, CaughtPanic, DropPolicy, Payload};
+use std::{
+    backtrace::BacktraceStatus,
+    fmt,
+    io::{self, Write},
+    panic::AssertUnwindSafe,
+    thread,
+};
+
+/// Receives caught panics before their payloads are disposed of
+pub trait PanicReporter: Send + Sync {
+    /// Report a caught panic
+    fn report(&self, payload: &Payload);
+
+    /// Report a caught panic with captured information. By default this calls
+    /// [`report`](Self::report) with the payload.
+    #[inline]
+    fn report_caught(&self, panic: &CaughtPanic) {
+        self.report(panic.payload())
+    }
+}
+
+impl<F: Fn(&Payload) + Send + Sync> PanicReporter for F {
+    #[inline]
+    fn report(&self, payload: &Payload) {
+        self(payload)
+    }
+}
+
+/// Reporter that writes the panic message to stderr
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StderrReporter;
+
+impl PanicReporter for StderrReporter {
+    fn report(&self, payload: &Payload) {
+        let thread = thread::current();
+        let name = thread.name().unwrap_or("<unnamed>");
+        let _ = match payload.message() {
+            Some(msg) => writeln!(io::stderr(), "thread '{name}' panicked: {msg}"),
+            None => writeln!(io::stderr(), "thread '{name}' panicked"),
+        };
+    }
+
+    fn report_caught(&self, panic: &CaughtPanic) {
+        let name = panic.thread_name().unwrap_or("<unnamed>");
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "thread '{name}' panicked");
+        if let Some(location) = panic.location() {
+            let _ = write!(stderr, " at {location}");
+        }
+        let _ = match panic.message() {
+            Some(msg) => writeln!(stderr, ":\n{msg}"),
+            None => writeln!(stderr),
+        };
+        if let Some(assertion) = panic.unwind_safety_assertion() {
+            let _ = writeln!(stderr, "{assertion}");
+        }
+    }
+}
+
+/// Formatter for a caught panic, for printing a readable report of panics caught at the top
+/// level of an application.
+///
+/// The report has the thread name, location and message of the panic, followed by the backtrace
+/// if it was captured. Consecutive repeated frames in the backtrace, e.g. from recursion, are
+/// collapsed into a single line.
+///
+/// ```
+/// use catch_unwind::{catch_unwind_captured, Report};
+///
+/// let panic = catch_unwind_captured(|| panic!("oh no")).unwrap_err();
+/// eprintln!("{}", Report::new(&panic).color(true));
+/// ```
+#[derive(Clone, Copy)]
+pub struct Report<'a> {
+    panic: &'a CaughtPanic,
+    color: bool,
+    backtrace: bool,
+}
+
+impl<'a> Report<'a> {
+    /// Create a new report for `panic`, without color and with the backtrace
+    #[inline]
+    pub fn new(panic: &'a CaughtPanic) -> Self {
+        Self {
+            panic,
+            color: false,
+            backtrace: true,
+        }
+    }
+
+    /// Set whether the report is colored with ANSI escape codes
+    #[inline]
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Set whether the backtrace is included if it was captured
+    #[inline]
+    pub fn backtrace(mut self, backtrace: bool) -> Self {
+        self.backtrace = backtrace;
+        self
+    }
+
+    fn style(&self, code: &'static str) -> &'static str {
+        if self.color {
+            code
+        } else {
+            ""
+        }
+    }
+}
+
+const RED: &str = "\x1b[1;31m";
+const CYAN: &str = "\x1b[36m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (red, cyan, dim, reset) = (
+            self.style(RED),
+            self.style(CYAN),
+            self.style(DIM),
+            self.style(RESET),
+        );
+        let name = self.panic.thread_name().unwrap_or("<unnamed>");
+        write!(f, "thread '{name}' {red}panicked{reset}")?;
+        if let Some(location) = self.panic.location() {
+            write!(f, " at {cyan}{location}{reset}")?;
+        }
+        write!(f, ":\n{}", panic_message!(self.panic))?;
+        if let Some(assertion) = self.panic.unwind_safety_assertion() {
+            write!(f, "\n{dim}{assertion}{reset}")?;
+        }
+
+        let Some(backtrace) = self.panic.backtrace().filter(|_| self.backtrace) else {
+            return Ok(());
+        };
+        if backtrace.status() != BacktraceStatus::Captured {
+            return Ok(());
+        }
+        write!(f, "\n\nstack backtrace:")?;
+        let backtrace = backtrace.to_string();
+        let mut frames = backtrace_frames(&backtrace).peekable();
+        while let Some((number, frame)) = frames.next() {
+            let mut repeated = 0;
+            while frames.next_if(|(_, next)| *next == frame).is_some() {
+                repeated += 1;
+            }
+            let mut lines = frame.iter();
+            if let Some(symbol) = lines.next() {
+                write!(f, "\n{number:>4}: {symbol}")?;
+            }
+            for line in lines {
+                write!(f, "\n      {dim}{line}{reset}")?;
+            }
+            if repeated != 0 {
+                write!(f, "\n      {dim}[... {repeated} repeated frames]{reset}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split a formatted backtrace into frames, each with its number and trimmed lines
+fn backtrace_frames(backtrace: &str) -> impl Iterator<Item = (&str, Vec<&str>)> {
+    let mut lines = backtrace.lines().map(str::trim).peekable();
+    std::iter::from_fn(move || loop {
+        let line = lines.next()?;
+        let Some((number, symbol)) = line.split_once(": ") else {
+            continue;
+        };
+        if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+            continue;
+        }
+        let mut frame = vec![symbol];
+        while let Some(line) = lines.next_if(|line| line.starts_with("at ")) {
+            frame.push(line);
+        }
+        return Some((number, frame));
+    })
+}
+
+/// Call `reporter`, handling any panic it raises according to `policy`
+pub(crate) fn report_guarded(reporter: &dyn PanicReporter, payload: &Payload, policy: DropPolicy) {
+    let _ = catch_unwind_with_policy(policy, AssertUnwindSafe(|| reporter.report(payload)));
+}
+
+/// Call `reporter` with a caught panic, handling any panic it raises according to `policy`
+pub(crate) fn report_caught_guarded(
+    reporter: &dyn PanicReporter,
+    panic: &CaughtPanic,
+    policy: DropPolicy,
+) {
+    let _ = catch_unwind_with_policy(policy, AssertUnwindSafe(|| reporter.report_caught(panic)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catch_unwind_captured;
+
+    #[test]
+    fn test_report() {
+        let panic = catch_unwind_captured(|| panic!("test")).unwrap_err();
+        let report = Report::new(&panic).backtrace(false).to_string();
+        assert!(report.contains(concat!("panicked at ", file!())));
+        assert!(report.ends_with(":\ntest"));
+        let report = Report::new(&panic).color(true).to_string();
+        assert!(report.contains("\x1b[1;31mpanicked\x1b[0m"));
+    }
+
+    #[test]
+    fn test_backtrace_frames() {
+        let backtrace = "   0: a\n             at a.rs:1:1\n   1: b\n   2: b\n   3: c";
+        let frames = backtrace_frames(backtrace).collect::<Vec<_>>();
+        assert_eq!(
+            frames,
+            [
+                ("0", vec!["a", "at a.rs:1:1"]),
+                ("1", vec!["b"]),
+                ("2", vec!["b"]),
+                ("3", vec!["c"])
+            ]
+        );
+    }
+}
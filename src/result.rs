@@ -0,0 +1,70 @@
+//! Discarding error values under panic protection
+
+use crate::{drop_or_abort, drop_or_forget, drop_or_log, drop_with_policy, DropPolicy};
+
+/// Extension trait for discarding the error of a [`Result`] whose destructor may panic, e.g.
+/// an error type that wraps an FFI handle
+pub trait ResultExt<T, E> {
+    /// Convert into an `Option`, like [`Result::ok`], dropping the error if there is one. If
+    /// dropping it panics, abort the process. See [`drop_or_abort`].
+    ///
+    /// ```
+    /// use catch_unwind::result::ResultExt;
+    ///
+    /// let result: Result<i32, String> = Err("oh no".into());
+    /// assert_eq!(result.drop_err_or_abort(), None);
+    /// ```
+    fn drop_err_or_abort(self) -> Option<T>;
+
+    /// Convert into an `Option`, like [`Result::ok`], dropping the error if there is one. If
+    /// dropping it panics, `mem::forget` the new panic payload. See [`drop_or_forget`].
+    fn drop_err_or_forget(self) -> Option<T>;
+
+    /// Convert into an `Option`, like [`Result::ok`], dropping the error if there is one. If
+    /// dropping it panics, write a message to stderr and `mem::forget` the new panic payload.
+    /// See [`drop_or_log`].
+    fn drop_err_or_log(self) -> Option<T>;
+
+    /// Convert into an `Option`, like [`Result::ok`], dropping the error if there is one and
+    /// handling a panic on drop according to `policy`. See [`drop_with_policy`].
+    fn drop_err_with_policy(self, policy: DropPolicy) -> Option<T>;
+}
+
+impl<T, E> ResultExt<T, E> for Result<T, E> {
+    #[inline]
+    fn drop_err_or_abort(self) -> Option<T> {
+        self.map_err(drop_or_abort).ok()
+    }
+
+    #[inline]
+    fn drop_err_or_forget(self) -> Option<T> {
+        self.map_err(drop_or_forget).ok()
+    }
+
+    #[inline]
+    fn drop_err_or_log(self) -> Option<T> {
+        self.map_err(drop_or_log).ok()
+    }
+
+    #[inline]
+    fn drop_err_with_policy(self, policy: DropPolicy) -> Option<T> {
+        self.map_err(|err| drop_with_policy(err, policy)).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::Bomb;
+
+    #[test]
+    fn test_drop_err() {
+        assert_eq!(Ok::<_, Bomb>(1).drop_err_or_abort(), Some(1));
+        assert_eq!(Err::<i32, _>(Bomb).drop_err_or_forget(), None);
+        assert_eq!(Err::<i32, _>(Bomb).drop_err_or_log(), None);
+        assert_eq!(
+            Err::<i32, _>(Bomb).drop_err_with_policy(DropPolicy::Forget),
+            None
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! Runtime support that differs between `std` and `no_std` builds
+
+#[cfg(not(feature = "abort-only"))]
+use crate::DropPolicy;
+use core::fmt;
+
+#[cfg(feature = "std")]
+pub(crate) use crate::abort::abort;
+#[cfg(all(feature = "std", not(panic = "abort")))]
+pub(crate) use std::panic::catch_unwind;
+#[cfg(all(feature = "std", not(feature = "abort-only")))]
+pub(crate) use std::panic::resume_unwind;
+
+#[cfg(all(not(feature = "std"), not(panic = "abort")))]
+pub(crate) use unwinding::panic::catch_unwind;
+
+/// Invoke the provided closure. Panics can't unwind with the `abort` panic strategy, so there's
+/// nothing to catch, and this compiles down to a plain call.
+#[cfg(panic = "abort")]
+#[inline(always)]
+pub(crate) fn catch_unwind<F: FnOnce() -> R + core::panic::UnwindSafe, R>(
+    f: F,
+) -> Result<R, alloc::boxed::Box<dyn core::any::Any + Send + 'static>> {
+    Ok(f())
+}
+
+/// Resume unwinding with a payload
+#[cfg(all(not(feature = "std"), not(feature = "abort-only")))]
+pub(crate) fn resume_unwind(payload: alloc::boxed::Box<dyn core::any::Any + Send>) -> ! {
+    unwinding::panic::begin_panic(payload);
+    abort()
+}
+
+/// Abort by panicking in a function that can't unwind, which calls the panic handler without
+/// unwinding
+#[cfg(not(feature = "std"))]
+pub(crate) fn abort() -> ! {
+    extern "C" fn abort() -> ! {
+        panic!("catch-unwind: aborting")
+    }
+    abort()
+}
+
+/// Get the default policy for the current thread
+#[cfg(not(feature = "abort-only"))]
+#[inline]
+pub(crate) fn default_policy() -> DropPolicy {
+    #[cfg(feature = "std")]
+    return crate::thread::default_policy();
+    #[cfg(not(feature = "std"))]
+    return crate::default_drop_policy();
+}
+
+/// Get the default policy selected by the environment, if any
+#[cfg(not(feature = "abort-only"))]
+#[inline]
+pub(crate) fn env_policy() -> Option<DropPolicy> {
+    #[cfg(feature = "std")]
+    return std::env::var(crate::POLICY_ENV_VAR)
+        .ok()
+        .and_then(|value| DropPolicy::from_env_value(&value));
+    #[cfg(not(feature = "std"))]
+    return None;
+}
+
+/// Location of the panic from the last guarded drop on the current thread, formatted as
+/// ` at <location>` if it was captured and nothing otherwise. It's only captured with `std`
+/// when verbose drop diagnostics are enabled, and never with `abort-only`.
+pub(crate) struct DropLocation(
+    #[cfg(all(feature = "std", not(feature = "abort-only")))]
+    pub(crate)  Option<crate::PanicLocation>,
+);
+
+impl fmt::Display for DropLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        #[cfg(all(feature = "std", not(feature = "abort-only")))]
+        if let Some(location) = &self.0 {
+            write!(f, " at {location}")?;
+        }
+        let _ = f;
+        Ok(())
+    }
+}
+
+/// Take the location of the panic from the last guarded drop on the current thread. See
+/// [`DropLocation`].
+#[inline]
+pub(crate) fn drop_location() -> DropLocation {
+    #[cfg(all(feature = "std", not(feature = "abort-only")))]
+    return DropLocation(crate::capture::take_drop_location());
+    #[cfg(any(not(feature = "std"), feature = "abort-only"))]
+    return DropLocation();
+}
+
+/// Run a guarded drop. With `std`, the location of a panic from it is captured if verbose drop
+/// diagnostics are enabled.
+#[inline]
+pub(crate) fn with_drop_capture<R>(f: impl FnOnce() -> R) -> R {
+    #[cfg(all(feature = "std", not(feature = "abort-only")))]
+    return crate::capture::with_drop_capture(f);
+    #[cfg(any(not(feature = "std"), feature = "abort-only"))]
+    return f();
+}
+
+/// Write a line to stderr. This does nothing without `std`. See [`Line`].
+#[inline]
+pub(crate) fn log(args: fmt::Arguments) {
+    #[cfg(feature = "std")]
+    {
+        let mut line = Line::new();
+        let _ = fmt::Write::write_fmt(&mut line, args);
+        line.emit()
+    }
+    #[cfg(not(feature = "std"))]
+    let _ = args;
+}
+
+/// Line of diagnostic output, formatted into a fixed buffer on the stack and written to stderr
+/// with a single raw write, without allocating or locking, so it can be written while aborting
+/// from inside an allocator or a signal handler. Output that doesn't fit is truncated.
+#[cfg(feature = "std")]
+pub(crate) struct Line {
+    buf: [u8; Self::CAPACITY],
+    len: usize,
+}
+
+#[cfg(feature = "std")]
+impl Line {
+    const CAPACITY: usize = 512;
+
+    #[inline]
+    pub(crate) const fn new() -> Self {
+        Self {
+            buf: [0; Self::CAPACITY],
+            len: 0,
+        }
+    }
+
+    /// Write the line to stderr, followed by a newline
+    pub(crate) fn emit(mut self) {
+        let len = self.len.min(Self::CAPACITY - 1);
+        self.buf[len] = b'\n';
+        write_stderr(&self.buf[..=len])
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // keep room for the newline
+        let available = Self::CAPACITY - 1 - self.len;
+        let n = s.len().min(available);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+/// Write bytes to stderr with `write(2)` or `WriteFile`, bypassing the lock and buffer of
+/// [`std::io::Stderr`]
+#[cfg(feature = "std")]
+fn write_stderr(bytes: &[u8]) {
+    use std::io::Write;
+
+    #[cfg(unix)]
+    let file = {
+        use std::os::fd::{AsRawFd, FromRawFd};
+        // SAFETY: the file is never dropped, so stderr isn't closed
+        unsafe { std::fs::File::from_raw_fd(std::io::stderr().as_raw_fd()) }
+    };
+    #[cfg(windows)]
+    let file = {
+        use std::os::windows::io::{AsRawHandle, FromRawHandle};
+        // SAFETY: the file is never dropped, so stderr isn't closed
+        unsafe { std::fs::File::from_raw_handle(std::io::stderr().as_raw_handle()) }
+    };
+    #[cfg(any(unix, windows))]
+    let _ = (&*core::mem::ManuallyDrop::new(file)).write_all(bytes);
+    #[cfg(not(any(unix, windows)))]
+    let _ = std::io::stderr().write_all(bytes);
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+
+    #[test]
+    fn test_line() {
+        let mut line = Line::new();
+        write!(line, "{}", "x".repeat(Line::CAPACITY * 2)).unwrap();
+        assert_eq!(line.len, Line::CAPACITY - 1);
+    }
+}
@@ -0,0 +1,85 @@
+//! Reporting caught panics to [Sentry](::sentry_core)
+
+use crate::{panic_message, CaughtPanic, PanicReporter, Payload};
+use ::sentry_core::{
+    capture_event,
+    protocol::{Event, Exception, Level, Mechanism, Thread},
+};
+use std::{backtrace::BacktraceStatus, thread};
+
+/// Reporter that sends caught panics to Sentry as error events, through the current hub.
+///
+/// The event has a `panic` exception with the panic message, marked as handled, and includes
+/// the location, backtrace and thread name of the panic if they were captured.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SentryReporter;
+
+impl PanicReporter for SentryReporter {
+    fn report(&self, payload: &Payload) {
+        let mut event = event(panic_message!(payload));
+        event.threads = vec![thread_info(thread::current().name())].into();
+        capture_event(event);
+    }
+
+    fn report_caught(&self, panic: &CaughtPanic) {
+        capture_event(panic_event(panic));
+    }
+}
+
+/// Create a Sentry event for a caught panic, like [`SentryReporter`] does
+pub fn panic_event(panic: &CaughtPanic) -> Event<'static> {
+    let mut event = event(panic_message!(panic));
+    event.culprit = panic.location().map(ToString::to_string);
+    event.threads = vec![thread_info(panic.thread_name())].into();
+    if let Some(backtrace) = panic.backtrace() {
+        if backtrace.status() == BacktraceStatus::Captured {
+            event
+                .extra
+                .insert("backtrace".into(), backtrace.to_string().into());
+        }
+    }
+    event
+}
+
+fn event(message: &str) -> Event<'static> {
+    Event {
+        level: Level::Error,
+        exception: vec![Exception {
+            ty: "panic".into(),
+            value: Some(message.into()),
+            mechanism: Some(Mechanism {
+                ty: "panic".into(),
+                handled: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }]
+        .into(),
+        ..Default::default()
+    }
+}
+
+fn thread_info(name: Option<&str>) -> Thread {
+    Thread {
+        name: name.map(String::from),
+        current: true,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catch_unwind_captured;
+
+    #[test]
+    fn test_panic_event() {
+        let panic = catch_unwind_captured(|| panic!("test")).unwrap_err();
+        let event = panic_event(&panic);
+        assert_eq!(event.exception[0].value.as_deref(), Some("test"));
+        assert!(event.culprit.unwrap().starts_with(file!()));
+        assert_eq!(event.threads[0].name.as_deref(), thread::current().name());
+
+        SentryReporter.report_caught(&panic);
+    }
+}
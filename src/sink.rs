@@ -0,0 +1,170 @@
+//! Catching panics in sinks
+
+use crate::{catch_unwind_wrapped, Payload};
+use futures_sink::Sink;
+use std::{
+    error::Error,
+    fmt,
+    panic::{AssertUnwindSafe, UnwindSafe},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Error from a [`CatchUnwindSink`]
+pub enum SinkError<E> {
+    /// The inner sink returned an error
+    Inner(E),
+
+    /// The inner sink panicked
+    Panic(Payload),
+}
+
+impl<E: fmt::Debug> fmt::Debug for SinkError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Inner(err) => f.debug_tuple("Inner").field(err).finish(),
+            Self::Panic(payload) => f
+                .debug_tuple("Panic")
+                .field(&payload.message().unwrap_or("Box<dyn Any>"))
+                .finish(),
+        }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for SinkError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Inner(err) => write!(f, "{err}"),
+            Self::Panic(payload) => match payload.message() {
+                Some(msg) => write!(f, "sink panicked: {msg}"),
+                None => write!(f, "sink panicked"),
+            },
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for SinkError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Inner(err) => err.source(),
+            Self::Panic(_) => None,
+        }
+    }
+}
+
+/// Sink that catches unwinding panics from the inner sink. See [`catch_unwind_sink`].
+#[derive(Debug)]
+#[must_use = "sinks do nothing unless polled"]
+pub struct CatchUnwindSink<S> {
+    inner: S,
+}
+
+impl<S> CatchUnwindSink<S> {
+    #[inline]
+    fn project(self: Pin<&mut Self>) -> Pin<&mut S> {
+        // SAFETY: `inner` is structurally pinned
+        unsafe { self.map_unchecked_mut(|this| &mut this.inner) }
+    }
+}
+
+fn catch_poll<E>(f: impl FnOnce() -> Poll<Result<(), E>>) -> Poll<Result<(), SinkError<E>>> {
+    match catch_unwind_wrapped(AssertUnwindSafe(f)) {
+        Ok(poll) => poll.map(|result| result.map_err(SinkError::Inner)),
+        Err(payload) => Poll::Ready(Err(SinkError::Panic(payload))),
+    }
+}
+
+impl<S: Sink<Item> + UnwindSafe, Item> Sink<Item> for CatchUnwindSink<S> {
+    type Error = SinkError<S::Error>;
+
+    #[inline]
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let inner = self.project();
+        catch_poll(|| inner.poll_ready(cx))
+    }
+
+    #[inline]
+    fn start_send(self: Pin<&mut Self>, item: Item) -> Result<(), Self::Error> {
+        let inner = self.project();
+        match catch_unwind_wrapped(AssertUnwindSafe(|| inner.start_send(item))) {
+            Ok(result) => result.map_err(SinkError::Inner),
+            Err(payload) => Err(SinkError::Panic(payload)),
+        }
+    }
+
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let inner = self.project();
+        catch_poll(|| inner.poll_flush(cx))
+    }
+
+    #[inline]
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let inner = self.project();
+        catch_poll(|| inner.poll_close(cx))
+    }
+}
+
+/// Wrap a sink to catch any unwinding panics that may occur in it. A caught panic is returned
+/// as [`SinkError::Panic`].
+///
+/// See [`std::panic::catch_unwind`] for more information.
+#[inline]
+pub fn catch_unwind_sink<S: UnwindSafe>(sink: S) -> CatchUnwindSink<S> {
+    CatchUnwindSink { inner: sink }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::endless_panic;
+    use futures::{executor::block_on, sink, SinkExt};
+    use std::{convert::Infallible, future};
+
+    #[test]
+    fn test_catch_unwind_sink() {
+        let mut sink = catch_unwind_sink(sink::drain::<u32>().with(|i| {
+            if i == 1 {
+                endless_panic()
+            }
+            future::ready(Ok::<_, Infallible>(i))
+        }));
+        assert!(block_on(sink.send(0)).is_ok());
+        match block_on(sink.send(1)) {
+            Err(SinkError::Panic(payload)) => payload.drop_or_forget(),
+            _ => panic!("panic wasn't caught"),
+        }
+    }
+
+    #[test]
+    fn test_sink_error() {
+        #[derive(Debug)]
+        struct Outer(fmt::Error);
+
+        impl fmt::Display for Outer {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "outer")
+            }
+        }
+
+        impl Error for Outer {
+            fn source(&self) -> Option<&(dyn Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let err = SinkError::Inner(Outer(fmt::Error));
+        assert_eq!(err.to_string(), "outer");
+        assert_eq!(format!("{err:?}"), "Inner(Outer(Error))");
+        assert!(err.source().unwrap().is::<fmt::Error>());
+
+        let mut sink = catch_unwind_sink(
+            sink::drain::<u32>()
+                .with(|_| -> future::Ready<Result<u32, Infallible>> { panic!("test") }),
+        );
+        let err = block_on(sink.send(0)).unwrap_err();
+        assert_eq!(err.to_string(), "sink panicked: test");
+        assert_eq!(format!("{err:?}"), r#"Panic("test")"#);
+        assert!(err.source().is_none());
+    }
+}
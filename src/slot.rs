@@ -0,0 +1,96 @@
+//! Replacing and destroying values in place under panic protection
+
+use crate::{drop_or_abort, drop_or_forget, drop_with_policy, DropPolicy};
+use core::mem::{self, ManuallyDrop};
+
+/// Replace the value at `dest` with `new`, and drop the old value, handling a panic on drop
+/// according to `policy`. This is a guarded version of `*dest = new`, which drops the old value
+/// without protection.
+///
+/// Unlike [`mem::replace`], the old value isn't returned, because dropping it here is what makes
+/// the replacement guarded. Use `mem::replace` and a guarded drop function on the result to
+/// inspect the old value first.
+///
+/// If the old value panics when dropped, `dest` already holds the new value.
+///
+/// ```
+/// use catch_unwind::{slot::replace_and_drop_guarded, DropPolicy};
+///
+/// let mut state = String::from("old");
+/// replace_and_drop_guarded(&mut state, String::from("new"), DropPolicy::Abort);
+/// assert_eq!(state, "new");
+/// ```
+#[inline]
+pub fn replace_and_drop_guarded<T>(dest: &mut T, new: T, policy: DropPolicy) {
+    drop_with_policy(mem::replace(dest, new), policy)
+}
+
+/// Replace the value at `dest` with its default value, and drop the old value, handling a panic
+/// on drop according to `policy`. See [`replace_and_drop_guarded`].
+#[inline]
+pub fn take_drop_guarded<T: Default>(dest: &mut T, policy: DropPolicy) {
+    drop_with_policy(mem::take(dest), policy)
+}
+
+/// Take the value out of a [`ManuallyDrop`] and drop it. If dropping it panics, abort the
+/// process. See [`drop_or_abort`].
+///
+/// # Safety
+///
+/// The value in `slot` must be initialized, and it must not be used again afterwards unless
+/// it's overwritten with a new value first, like [`ManuallyDrop::take`].
+///
+/// ```
+/// use catch_unwind::slot::finish_or_abort;
+/// use std::mem::ManuallyDrop;
+///
+/// let mut slot = ManuallyDrop::new(String::from("staged"));
+/// // SAFETY: `slot` isn't used again
+/// unsafe { finish_or_abort(&mut slot) };
+/// ```
+#[inline]
+pub unsafe fn finish_or_abort<T>(slot: &mut ManuallyDrop<T>) {
+    // SAFETY: guaranteed by the caller
+    drop_or_abort(unsafe { ManuallyDrop::take(slot) })
+}
+
+/// Take the value out of a [`ManuallyDrop`] and drop it. If dropping it panics, `mem::forget`
+/// the new panic payload. See [`drop_or_forget`].
+///
+/// # Safety
+///
+/// The value in `slot` must be initialized, and it must not be used again afterwards unless
+/// it's overwritten with a new value first, like [`ManuallyDrop::take`].
+#[inline]
+pub unsafe fn finish_or_forget<T>(slot: &mut ManuallyDrop<T>) {
+    // SAFETY: guaranteed by the caller
+    drop_or_forget(unsafe { ManuallyDrop::take(slot) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::Bomb;
+
+    #[test]
+    fn test_replace_and_drop_guarded() {
+        let mut value = Some(Bomb);
+        replace_and_drop_guarded(&mut value, None, DropPolicy::Forget);
+        assert!(value.is_none());
+
+        let mut value = Some(Bomb);
+        take_drop_guarded(&mut value, DropPolicy::Log);
+        assert!(value.is_none());
+    }
+
+    #[test]
+    fn test_finish() {
+        let mut slot = ManuallyDrop::new(Bomb);
+        // SAFETY: `slot` isn't used again
+        unsafe { finish_or_forget(&mut slot) };
+
+        let mut slot = ManuallyDrop::new(String::from("test"));
+        // SAFETY: `slot` isn't used again
+        unsafe { finish_or_abort(&mut slot) };
+    }
+}
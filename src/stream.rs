@@ -0,0 +1,116 @@
+//! Catching panics in streams
+
+use crate::{catch_unwind_wrapped, Payload};
+use futures_core::Stream;
+use std::{
+    panic::{AssertUnwindSafe, UnwindSafe},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Stream that catches unwinding panics from polling the inner stream. See
+/// [`catch_unwind_stream`].
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct CatchUnwindStream<S> {
+    inner: S,
+    fuse_on_panic: bool,
+    done: bool,
+}
+
+impl<S> CatchUnwindStream<S> {
+    /// End the stream after the first caught panic, instead of continuing to poll the inner
+    /// stream
+    #[inline]
+    pub fn fuse_on_panic(mut self) -> Self {
+        self.fuse_on_panic = true;
+        self
+    }
+
+    #[inline]
+    fn project(self: Pin<&mut Self>) -> (Pin<&mut S>, bool, &mut bool) {
+        // SAFETY: `inner` is structurally pinned, and the other fields aren't
+        unsafe {
+            let this = self.get_unchecked_mut();
+            (
+                Pin::new_unchecked(&mut this.inner),
+                this.fuse_on_panic,
+                &mut this.done,
+            )
+        }
+    }
+}
+
+impl<S: Stream + UnwindSafe> Stream for CatchUnwindStream<S> {
+    type Item = Result<S::Item, Payload>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let (inner, fuse_on_panic, done) = self.project();
+        if *done {
+            return Poll::Ready(None);
+        }
+        match catch_unwind_wrapped(AssertUnwindSafe(|| inner.poll_next(cx))) {
+            Ok(Poll::Ready(None)) => {
+                *done = true;
+                Poll::Ready(None)
+            }
+            Ok(poll) => poll.map(|item| item.map(Ok)),
+            Err(payload) => {
+                *done = fuse_on_panic;
+                Poll::Ready(Some(Err(payload)))
+            }
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            (0, Some(0))
+        } else {
+            self.inner.size_hint()
+        }
+    }
+}
+
+/// Wrap a stream to catch any unwinding panics that may occur while producing each item. A
+/// caught panic is yielded as an `Err(Payload)` item, and the stream continues with the next
+/// item. Use [`CatchUnwindStream::fuse_on_panic`] to end the stream after a panic instead.
+///
+/// See [`std::panic::catch_unwind`] for more information.
+#[inline]
+pub fn catch_unwind_stream<S: Stream + UnwindSafe>(stream: S) -> CatchUnwindStream<S> {
+    CatchUnwindStream {
+        inner: stream,
+        fuse_on_panic: false,
+        done: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::endless_panic;
+    use futures::{executor::block_on_stream, stream, StreamExt};
+
+    fn items() -> impl Stream<Item = u32> + UnwindSafe {
+        stream::iter(0..3).map(|i| {
+            if i == 1 {
+                endless_panic()
+            }
+            i
+        })
+    }
+
+    #[test]
+    fn test_catch_unwind_stream() {
+        let results: Vec<_> = block_on_stream(catch_unwind_stream(items()))
+            .map(|item| item.map_err(Payload::drop_or_forget))
+            .collect();
+        assert_eq!(results, [Ok(0), Err(()), Ok(2)]);
+
+        let results: Vec<_> = block_on_stream(catch_unwind_stream(items()).fuse_on_panic())
+            .map(|item| item.map_err(Payload::drop_or_forget))
+            .collect();
+        assert_eq!(results, [Ok(0), Err(())]);
+    }
+}
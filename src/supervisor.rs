@@ -0,0 +1,237 @@
+//! Restarting work that panics
+
+use crate::{
+    catch_unwind_captured, catch_unwind_with_policy, report::report_caught_guarded,
+    thread::default_policy, CaughtPanic, PanicReporter,
+};
+use std::{
+    fmt,
+    panic::{AssertUnwindSafe, UnwindSafe},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+/// Policy for restarting work that panics, with a limit on the number of restarts and
+/// exponential backoff between them
+#[derive(Clone)]
+pub struct RestartPolicy {
+    max_restarts: Option<u32>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    reporter: Option<Arc<dyn PanicReporter>>,
+}
+
+impl Default for RestartPolicy {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RestartPolicy {
+    /// Create a new restart policy. By default there's no limit on the number of restarts, the
+    /// backoff starts at 100 ms and doubles after each restart up to 30 seconds, and caught
+    /// panics aren't reported.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            max_restarts: None,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            reporter: None,
+        }
+    }
+
+    /// Set the maximum number of restarts. After this many restarts, the next caught panic is
+    /// returned instead of restarting.
+    #[inline]
+    pub fn max_restarts(mut self, max_restarts: u32) -> Self {
+        self.max_restarts = Some(max_restarts);
+        self
+    }
+
+    /// Set the backoff before the first restart, and the maximum backoff. The backoff doubles
+    /// after each restart.
+    #[inline]
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Set the reporter that caught panics are sent to
+    #[inline]
+    pub fn reporter(mut self, reporter: impl PanicReporter + 'static) -> Self {
+        self.reporter = Some(Arc::new(reporter));
+        self
+    }
+
+    /// Get the backoff before restart number `restart`, counting from zero
+    #[inline]
+    pub fn backoff_for(&self, restart: u32) -> Duration {
+        self.initial_backoff
+            .checked_mul(1 << restart.min(31))
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+
+    /// Check if another restart is allowed after `restarts` restarts
+    #[inline]
+    pub fn can_restart(&self, restarts: u32) -> bool {
+        self.max_restarts.is_none_or(|max| restarts < max)
+    }
+
+    /// Report a caught panic to the configured reporter, if any. A panic from the reporter is
+    /// caught and dropped according to the current thread's default policy.
+    pub fn report(&self, panic: &CaughtPanic) {
+        if let Some(reporter) = &self.reporter {
+            report_caught_guarded(&**reporter, panic, default_policy());
+        }
+    }
+}
+
+/// Callback called by a [`Supervisor`] for each caught panic, with the number of restarts so far
+type FailureCallback = Arc<dyn Fn(&CaughtPanic, u32) + Send + Sync>;
+
+/// Runs a job and restarts it on the current thread when it panics, according to a
+/// [`RestartPolicy`].
+///
+/// ```
+/// use catch_unwind::supervisor::{RestartPolicy, Supervisor};
+/// use std::{
+///     sync::atomic::{AtomicU32, Ordering},
+///     time::Duration,
+/// };
+///
+/// let supervisor = Supervisor::new(
+///     RestartPolicy::new()
+///         .max_restarts(3)
+///         .backoff(Duration::ZERO, Duration::ZERO),
+/// )
+/// .on_failure(|panic, restarts| eprintln!("restart {restarts}: {:?}", panic.message()));
+/// let attempts = AtomicU32::new(0);
+/// let result = supervisor.run(|| {
+///     let attempt = attempts.fetch_add(1, Ordering::Relaxed) + 1;
+///     assert!(attempt > 2, "not yet");
+///     attempt
+/// });
+/// assert!(matches!(result, Ok(3)));
+/// ```
+#[derive(Clone, Default)]
+pub struct Supervisor {
+    policy: RestartPolicy,
+    on_failure: Vec<FailureCallback>,
+}
+
+impl fmt::Debug for Supervisor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Supervisor").finish_non_exhaustive()
+    }
+}
+
+impl Supervisor {
+    /// Create a new supervisor with a restart policy
+    #[inline]
+    pub fn new(policy: RestartPolicy) -> Self {
+        Self {
+            policy,
+            on_failure: Vec::new(),
+        }
+    }
+
+    /// Add a callback that's called with each caught panic and the number of restarts so far,
+    /// after the panic is reported. A panic from the callback is caught and dropped according
+    /// to the current thread's default policy.
+    #[inline]
+    pub fn on_failure(
+        mut self,
+        callback: impl Fn(&CaughtPanic, u32) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_failure.push(Arc::new(callback));
+        self
+    }
+
+    /// Get the restart policy
+    #[inline]
+    pub fn policy(&self) -> &RestartPolicy {
+        &self.policy
+    }
+
+    /// Run `job` until it completes without panicking, restarting it after the policy's backoff
+    /// each time it panics.
+    ///
+    /// Each caught panic is reported to the policy's reporter, if any, and passed to the failure
+    /// callbacks. When no more restarts are allowed, the last caught panic is returned instead of
+    /// being dropped.
+    pub fn run<F: FnMut() -> R + UnwindSafe, R>(&self, mut job: F) -> Result<R, CaughtPanic> {
+        let mut restarts = 0;
+        loop {
+            match catch_unwind_captured(AssertUnwindSafe(&mut job)) {
+                Ok(ok) => return Ok(ok),
+                Err(panic) => {
+                    self.policy.report(&panic);
+                    for callback in &self.on_failure {
+                        let _ = catch_unwind_with_policy(
+                            default_policy(),
+                            AssertUnwindSafe(|| callback(&panic, restarts)),
+                        );
+                    }
+                    if !self.policy.can_restart(restarts) {
+                        return Err(panic);
+                    }
+                    panic.into_payload().drop_with_policy(default_policy());
+                    thread::sleep(self.policy.backoff_for(restarts));
+                    restarts += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_policy() {
+        let policy = RestartPolicy::new()
+            .max_restarts(2)
+            .backoff(Duration::from_secs(1), Duration::from_secs(3));
+        assert_eq!(policy.backoff_for(0), Duration::from_secs(1));
+        assert_eq!(policy.backoff_for(1), Duration::from_secs(2));
+        assert_eq!(policy.backoff_for(2), Duration::from_secs(3));
+        assert_eq!(policy.backoff_for(100), Duration::from_secs(3));
+        assert!(policy.can_restart(1));
+        assert!(!policy.can_restart(2));
+    }
+
+    #[test]
+    fn test_supervisor() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        static FAILURES: AtomicU32 = AtomicU32::new(0);
+
+        let supervisor = Supervisor::new(
+            RestartPolicy::new()
+                .max_restarts(1)
+                .backoff(Duration::ZERO, Duration::ZERO),
+        )
+        .on_failure(|panic, restarts| {
+            assert_eq!(panic.message(), Some("test"));
+            FAILURES.fetch_add(1, Ordering::Relaxed);
+            if restarts == 0 {
+                panic!("callback")
+            }
+        });
+        let attempts = AtomicU32::new(0);
+        let panic = supervisor
+            .run(|| {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                panic!("test")
+            })
+            .unwrap_err();
+        assert_eq!(panic.message(), Some("test"));
+        assert_eq!(attempts.load(Ordering::Relaxed), 2);
+        assert_eq!(FAILURES.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,314 @@
+//! Helpers for testing code that panics
+
+use crate::{
+    capture::with_hook_suppressed, catch_unwind_captured, catch_unwind_wrapped, panic_message,
+    CaughtPanic, PanicLocation,
+};
+use std::{
+    any::{type_name, Any},
+    cell::Cell,
+    error::Error,
+    fmt,
+    marker::PhantomData,
+    panic::{panic_any, UnwindSafe},
+};
+
+thread_local! {
+    static CAUGHT: Cell<usize> = const { Cell::new(0) };
+    static FORGOTTEN: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Count a panic caught by this crate on the current thread
+#[inline]
+pub(crate) fn count_caught() {
+    let _ = CAUGHT.try_with(|caught| caught.set(caught.get() + 1));
+}
+
+/// Count a payload forgotten by this crate on the current thread
+#[inline]
+pub(crate) fn count_forgotten() {
+    let _ = FORGOTTEN.try_with(|forgotten| forgotten.set(forgotten.get() + 1));
+}
+
+/// Invoke the provided closure and assert that exactly `expected` panic payloads were forgotten
+/// by this crate on the current thread while it ran, because they panicked when dropped. This
+/// can be used to check that code never silently hits the forget policy.
+///
+/// ```
+/// use catch_unwind::{catch_unwind_or_forget, testing::assert_forgotten_payloads};
+///
+/// assert_forgotten_payloads(0, || catch_unwind_or_forget(|| panic!("oh no")));
+/// ```
+///
+/// # Panics
+/// Panics if a different number of payloads was forgotten. Panics from the closure aren't
+/// caught.
+#[track_caller]
+pub fn assert_forgotten_payloads<R>(expected: usize, f: impl FnOnce() -> R) -> R {
+    let start = FORGOTTEN.with(Cell::get);
+    let result = f();
+    let forgotten = FORGOTTEN.with(Cell::get) - start;
+    assert_eq!(
+        forgotten, expected,
+        "expected {expected} forgotten payloads, but {forgotten} were forgotten"
+    );
+    result
+}
+
+/// Counter of the panics caught by this crate's functions on the current thread while it's
+/// alive, including panics from dropping payloads.
+///
+/// ```
+/// use catch_unwind::{catch_unwind_or_forget, testing::PanicCounter};
+///
+/// let counter = PanicCounter::new();
+/// let _ = catch_unwind_or_forget(|| panic!("oh no"));
+/// counter.assert_count(1);
+/// ```
+#[must_use = "the counter only counts panics caught while it's alive"]
+pub struct PanicCounter {
+    start: usize,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl PanicCounter {
+    /// Start counting caught panics
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            start: CAUGHT.with(Cell::get),
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Get the number of panics caught since the counter was created
+    #[inline]
+    pub fn count(&self) -> usize {
+        CAUGHT.with(Cell::get) - self.start
+    }
+
+    /// Assert that exactly `expected` panics were caught since the counter was created, and stop
+    /// counting
+    #[track_caller]
+    pub fn assert_count(self, expected: usize) {
+        let count = self.count();
+        assert_eq!(
+            count, expected,
+            "expected {expected} caught panics, but {count} were caught"
+        );
+    }
+}
+
+impl Default for PanicCounter {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Value that panics with another `PanicOnDrop` as the payload when dropped, so it panics
+/// whenever it's dropped without being forgotten.
+///
+/// Use `panic_any(PanicOnDrop)` to panic with a payload that can't be dropped.
+#[derive(Debug)]
+pub struct PanicOnDrop;
+
+impl Drop for PanicOnDrop {
+    fn drop(&mut self) {
+        panic_any(Self)
+    }
+}
+
+/// Value that panics when dropped with a payload of the same type with `depth` decreased by one,
+/// until `depth` is zero. Dropping it and each resulting payload in turn panics `depth` times.
+#[derive(Debug)]
+pub struct NestedPanicOnDrop {
+    /// Number of times dropping this value and its payloads panics
+    pub depth: usize,
+}
+
+impl Drop for NestedPanicOnDrop {
+    fn drop(&mut self) {
+        if self.depth != 0 {
+            panic_any(Self {
+                depth: self.depth - 1,
+            })
+        }
+    }
+}
+
+/// Invoke the provided closure, expecting it to panic with a payload of type `T`, and return
+/// the payload.
+///
+/// # Panics
+/// Panics with a diagnostic message if the closure doesn't panic, or if the payload isn't a
+/// `T`. A payload of another type is dropped, and forgotten if dropping it panics.
+#[track_caller]
+pub fn expect_panic<T: Any>(f: impl FnOnce() + UnwindSafe) -> Box<T> {
+    let payload = match catch_unwind_wrapped(f) {
+        Ok(()) => panic!("expected a panic with a `{}` payload", type_name::<T>()),
+        Err(payload) => payload,
+    };
+    if payload.get().is::<T>() {
+        return payload.into_inner().downcast().unwrap();
+    }
+    let message = panic_message!(payload).to_owned();
+    payload.drop_or_forget();
+    panic!(
+        "expected a panic with a `{}` payload, but it panicked with: {message}",
+        type_name::<T>()
+    )
+}
+
+/// Invoke the provided closure, expecting it to panic with a message containing `expected`, and
+/// return the caught panic for further assertions.
+///
+/// The panic hook that was installed before this crate's own isn't called for the panic, so
+/// expected panics aren't printed.
+///
+/// # Panics
+/// Panics with a diagnostic message if the closure doesn't panic, or if the panic message
+/// doesn't contain `expected`. The payload is dropped in that case, and forgotten if dropping
+/// it panics.
+#[track_caller]
+pub fn expect_panic_message(f: impl FnOnce() + UnwindSafe, expected: &str) -> CaughtPanic {
+    let panic = match with_hook_suppressed(|| catch_unwind_captured(f)) {
+        Ok(()) => panic!("expected a panic with a message containing {expected:?}"),
+        Err(panic) => panic,
+    };
+    if panic
+        .message()
+        .is_some_and(|message| message.contains(expected))
+    {
+        return panic;
+    }
+    let message = panic_message!(panic).to_owned();
+    panic.into_payload().drop_or_forget();
+    panic!(
+        "expected a panic with a message containing {expected:?}, but it panicked with: {message}"
+    )
+}
+
+/// Failure of a test case run with [`run_case_guarded`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CaseFailure {
+    message: String,
+    location: Option<PanicLocation>,
+}
+
+impl CaseFailure {
+    /// Get the panic message. This is `Box<dyn Any>` if the payload wasn't a string.
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Get the location the panic originated from, if it was captured
+    #[inline]
+    pub fn location(&self) -> Option<&PanicLocation> {
+        self.location.as_ref()
+    }
+}
+
+impl fmt::Display for CaseFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "panicked at {location}: {}", self.message),
+            None => write!(f, "panicked: {}", self.message),
+        }
+    }
+}
+
+impl Error for CaseFailure {}
+
+/// Run a property test case, converting a panic into a [`CaseFailure`] with its message and
+/// location, for use in property testing loops.
+///
+/// The payload is dropped before returning, and forgotten if dropping it panics, so a payload
+/// that panics when dropped can't end the loop.
+///
+/// ```
+/// use catch_unwind::testing::run_case_guarded;
+///
+/// let failures = (0..10)
+///     .filter_map(|n| run_case_guarded(|| assert!(n < 8, "{n} is too big")).err())
+///     .count();
+/// assert_eq!(failures, 2);
+/// ```
+pub fn run_case_guarded<R>(f: impl FnOnce() -> R + UnwindSafe) -> Result<R, CaseFailure> {
+    catch_unwind_captured(f).map_err(|panic| {
+        let failure = CaseFailure {
+            message: panic_message!(panic).to_owned(),
+            location: panic.location().cloned(),
+        };
+        panic.into_payload().drop_or_forget();
+        failure
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_panics;
+    use std::panic::panic_any;
+
+    #[test]
+    fn test_expect_panic() {
+        assert_eq!(*expect_panic::<i32>(|| panic_any(42)), 42);
+        assert_eq!(*expect_panic::<&str>(|| panic!("test")), "test");
+        assert_panics!(expect_panic::<i32>(|| ()), "expected a panic");
+        assert_panics!(expect_panic::<i32>(|| panic!("test")), "with: test");
+    }
+
+    #[test]
+    fn test_panic_counter() {
+        let counter = PanicCounter::new();
+        let _ = crate::catch_unwind_or_forget(|| panic!("test"));
+        let inner = PanicCounter::new();
+        let _ = crate::catch_unwind_or_forget(crate::tests::endless_panic);
+        inner.assert_count(2);
+        assert_eq!(counter.count(), 3);
+    }
+
+    #[test]
+    fn test_panic_on_drop() {
+        let counter = PanicCounter::new();
+        let mut payload = crate::drop_wrapped(NestedPanicOnDrop { depth: 3 });
+        while let Err(err) = payload {
+            payload = crate::drop_wrapped(err.into_inner());
+        }
+        counter.assert_count(3);
+
+        let payload = expect_panic::<PanicOnDrop>(|| panic_any(PanicOnDrop));
+        crate::drop_or_forget(payload);
+    }
+
+    #[test]
+    fn test_assert_forgotten_payloads() {
+        assert_forgotten_payloads(1, || {
+            let _ = crate::catch_unwind_or_forget(|| panic_any(PanicOnDrop));
+        });
+        assert_forgotten_payloads(0, || crate::drop_or_forget(NestedPanicOnDrop { depth: 0 }));
+        assert_panics!(assert_forgotten_payloads(0, || crate::drop_or_log(
+            PanicOnDrop
+        )));
+    }
+
+    #[test]
+    fn test_run_case_guarded() {
+        assert_eq!(run_case_guarded(|| 1), Ok(1));
+        let failure = run_case_guarded(|| panic!("test")).unwrap_err();
+        assert_eq!(failure.message(), "test");
+        assert_eq!(failure.location().unwrap().file(), file!());
+        let failure = run_case_guarded(|| panic_any(PanicOnDrop)).unwrap_err();
+        assert_eq!(failure.message(), "Box<dyn Any>");
+    }
+
+    #[test]
+    fn test_expect_panic_message() {
+        let panic = expect_panic_message(|| panic!("oh no"), "no");
+        assert_eq!(panic.location().unwrap().file(), file!());
+        assert_panics!(expect_panic_message(|| (), "no"), "expected a panic");
+        assert_panics!(expect_panic_message(|| panic!("test"), "no"), "with: test");
+    }
+}
@@ -0,0 +1,470 @@
+//! Spawning threads with caught panics
+
+use crate::{
+    abort::abort, catch_unwind_wrapped, default_drop_policy, handle_caught, hook::chain_hook,
+    payload_message, report::report_guarded, rt::Line, CatchPolicy, DropPolicy, MultiPayload,
+    PanicReporter,
+};
+use std::{
+    cell::Cell,
+    fmt::{self, Write},
+    io,
+    marker::PhantomData,
+    mem,
+    panic::{AssertUnwindSafe, PanicHookInfo},
+    sync::{Arc, Mutex, Once},
+    thread::{self, JoinHandle, Scope, ScopedJoinHandle},
+};
+
+thread_local! {
+    static DEFAULT_POLICY: Cell<Option<DropPolicy>> = const { Cell::new(None) };
+    static TLS_GUARD_EXITED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Set the default policy for handling a panic payload that panics when dropped on the current
+/// thread. This is used when dropping a [`Payload`](crate::Payload), and is inherited by threads
+/// spawned with this crate's spawn wrappers. It overrides the process-wide default set with
+/// [`set_default_drop_policy`](crate::set_default_drop_policy).
+#[inline]
+pub fn set_default_policy(policy: DropPolicy) {
+    inherit_policy(Some(policy))
+}
+
+/// Clear the default policy of the current thread, so the process-wide default is used again.
+/// See [`set_default_policy`].
+#[inline]
+pub fn clear_default_policy() {
+    inherit_policy(None)
+}
+
+/// Guard that restores the previous default policy of the current thread when dropped. See
+/// [`override_default_policy`].
+#[must_use = "the previous policy is restored when the guard is dropped"]
+pub struct PolicyGuard {
+    prev: Option<DropPolicy>,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for PolicyGuard {
+    #[inline]
+    fn drop(&mut self) {
+        inherit_policy(self.prev)
+    }
+}
+
+/// Override the default policy of the current thread until the returned guard is dropped.
+/// See [`set_default_policy`].
+#[inline]
+pub fn override_default_policy(policy: DropPolicy) -> PolicyGuard {
+    let prev = inherited_policy();
+    set_default_policy(policy);
+    PolicyGuard {
+        prev,
+        _not_send: PhantomData,
+    }
+}
+
+/// Invoke the provided closure with the default policy of the current thread overridden by
+/// `policy`. The previous default policy is restored afterwards, even if the closure panics.
+#[inline]
+pub fn with_drop_policy<F: FnOnce() -> R, R>(policy: DropPolicy, f: F) -> R {
+    let _guard = override_default_policy(policy);
+    f()
+}
+
+/// Get the default policy for handling a panic payload that panics when dropped on the current
+/// thread. This is the policy set with [`set_default_policy`] if any, and the process-wide
+/// default otherwise.
+#[inline]
+pub fn default_policy() -> DropPolicy {
+    inherited_policy().unwrap_or_else(default_drop_policy)
+}
+
+/// Get the policy set for the current thread, to be inherited by a spawned thread
+#[inline]
+pub(crate) fn inherited_policy() -> Option<DropPolicy> {
+    DEFAULT_POLICY.try_with(Cell::get).ok().flatten()
+}
+
+/// Set the policy for the current thread to the one inherited from the spawning thread
+#[inline]
+pub(crate) fn inherit_policy(policy: Option<DropPolicy>) {
+    let _ = DEFAULT_POLICY.try_with(|default| default.set(policy));
+}
+
+/// Thread factory like [`std::thread::Builder`], which also configures how panics in the
+/// spawned thread are handled.
+///
+/// Panics in the spawned thread are caught and reported to the configured [`PanicReporter`],
+/// if any, and then handled according to the configured [`CatchPolicy`], like with
+/// [`catch_unwind_with_policies`](crate::catch_unwind_with_policies). The payload is then
+/// dropped according to the configured [`DropPolicy`], or the spawning thread's default policy
+/// if none is configured.
+///
+/// The spawned thread inherits the spawning thread's default policy.
+pub struct Builder {
+    inner: thread::Builder,
+    policy: Option<DropPolicy>,
+    catch_policy: CatchPolicy,
+    reporter: Option<Arc<dyn PanicReporter>>,
+}
+
+impl Default for Builder {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Builder {
+    /// Create a new builder with the default configuration
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            inner: thread::Builder::new(),
+            policy: None,
+            catch_policy: CatchPolicy::Ignore,
+            reporter: None,
+        }
+    }
+
+    /// Set the name of the thread
+    #[inline]
+    pub fn name(mut self, name: String) -> Self {
+        self.inner = self.inner.name(name);
+        self
+    }
+
+    /// Set the stack size of the thread
+    #[inline]
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.inner = self.inner.stack_size(size);
+        self
+    }
+
+    /// Set the policy for handling a panic payload that panics when dropped
+    #[inline]
+    pub fn policy(mut self, policy: DropPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Set the policy for handling a caught panic, after it's been reported. The default is
+    /// [`CatchPolicy::Ignore`].
+    #[inline]
+    pub fn catch_policy(mut self, policy: CatchPolicy) -> Self {
+        self.catch_policy = policy;
+        self
+    }
+
+    /// Set the reporter that caught panics are sent to
+    #[inline]
+    pub fn reporter(mut self, reporter: impl PanicReporter + 'static) -> Self {
+        self.reporter = Some(Arc::new(reporter));
+        self
+    }
+
+    /// Spawn a new thread. The thread's result is `Some` if it didn't panic, and `None` otherwise.
+    ///
+    /// See [`std::thread::Builder::spawn`] for more information.
+    pub fn spawn<F, T>(self, f: F) -> io::Result<JoinHandle<Option<T>>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let Self {
+            inner,
+            policy,
+            catch_policy,
+            reporter,
+        } = self;
+        let inherited = inherited_policy();
+        let policy = policy.unwrap_or_else(default_policy);
+        inner.spawn(move || {
+            inherit_policy(inherited);
+            match catch_unwind_wrapped(AssertUnwindSafe(f)) {
+                Ok(ok) => Some(ok),
+                Err(payload) => {
+                    if let Some(reporter) = reporter {
+                        report_guarded(&*reporter, &payload, policy);
+                    }
+                    handle_caught(payload.get(), catch_policy);
+                    payload.drop_with_policy(policy);
+                    None
+                }
+            }
+        })
+    }
+}
+
+/// Scope for spawning threads with [`panic_scope`]
+pub struct PanicScope<'scope, 'env: 'scope> {
+    scope: &'scope Scope<'scope, 'env>,
+    panics: Arc<Mutex<MultiPayload>>,
+}
+
+impl fmt::Debug for PanicScope<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PanicScope").finish_non_exhaustive()
+    }
+}
+
+impl<'scope> PanicScope<'scope, '_> {
+    /// Spawn a new scoped thread. A panic in the thread is caught and collected by the scope.
+    /// The thread's result is `Some` if it didn't panic, and `None` otherwise.
+    ///
+    /// See [`std::thread::Scope::spawn`] for more information.
+    pub fn spawn<F, T>(&self, f: F) -> ScopedJoinHandle<'scope, Option<T>>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        let panics = self.panics.clone();
+        let inherited = inherited_policy();
+        self.scope.spawn(move || {
+            inherit_policy(inherited);
+            catch_unwind_wrapped(AssertUnwindSafe(f))
+                .map_err(|payload| {
+                    panics
+                        .lock()
+                        .unwrap_or_else(|err| err.into_inner())
+                        .push(payload)
+                })
+                .ok()
+        })
+    }
+}
+
+/// Create a scope for spawning threads, like [`std::thread::scope`], that collects the panics
+/// of every spawned thread instead of resuming one of them when the scope ends.
+///
+/// All the spawned threads are joined before this returns, even if `f` or some of the threads
+/// panic, so shutting down the remaining threads is never skipped. A panic in `f` itself is
+/// caught and collected too.
+///
+/// Returns `Ok` with the result of `f` if no panics were caught, and `Err(MultiPayload)` with
+/// every caught payload otherwise.
+///
+/// ```
+/// use catch_unwind::thread::panic_scope;
+///
+/// let result = panic_scope(|s| {
+///     s.spawn(|| panic!("first"));
+///     s.spawn(|| 1 + 1);
+///     s.spawn(|| panic!("second"));
+/// });
+/// let panics = result.unwrap_err();
+/// assert_eq!(panics.len(), 2);
+/// ```
+pub fn panic_scope<'env, F, R>(f: F) -> Result<R, MultiPayload>
+where
+    F: for<'scope> FnOnce(&PanicScope<'scope, 'env>) -> R,
+{
+    let panics = Arc::new(Mutex::new(MultiPayload::new()));
+    let result = thread::scope(|scope| {
+        let scope = PanicScope {
+            scope,
+            panics: panics.clone(),
+        };
+        // the children only share the collected panics, which stay consistent if `f` panics
+        catch_unwind_wrapped(AssertUnwindSafe(|| f(&scope)))
+    });
+    let mut panics = mem::take(&mut *panics.lock().unwrap_or_else(|err| err.into_inner()));
+    match result {
+        Ok(ok) if panics.is_empty() => Ok(ok),
+        Ok(_) => Err(panics),
+        Err(payload) => {
+            panics.push(payload);
+            Err(panics)
+        }
+    }
+}
+
+/// Run `f` as the body of the current thread, and guard against panics from thread-local
+/// destructors when the thread exits.
+///
+/// Unwinding out of a thread-local destructor can't be recovered from, and the default behavior
+/// is platform specific and makes it hard to tell what happened. Any panic on this thread after
+/// `f` has returned or unwound is treated as coming from a thread-local destructor, and aborts
+/// the process after writing a message with the panic's location to stderr.
+///
+/// `f` should contain everything the thread does, as code that runs after this function returns
+/// will also be treated as thread-local destruction.
+pub fn run_with_tls_guard<F: FnOnce() -> R, R>(f: F) -> R {
+    struct Exit;
+
+    impl Drop for Exit {
+        fn drop(&mut self) {
+            let _ = TLS_GUARD_EXITED.try_with(|exited| exited.set(true));
+        }
+    }
+
+    // the panic hook can't be changed while panicking
+    static INSTALL_HOOK: Once = Once::new();
+    if !thread::panicking() {
+        INSTALL_HOOK.call_once(install_tls_guard_hook);
+    }
+
+    let _ = TLS_GUARD_EXITED.try_with(|exited| exited.set(false));
+    let _exit = Exit;
+    f()
+}
+
+fn install_tls_guard_hook() {
+    chain_hook(|info| {
+        if TLS_GUARD_EXITED.try_with(Cell::get).unwrap_or(true) {
+            tls_destructor_panicked(info)
+        }
+    })
+    .keep()
+}
+
+fn tls_destructor_panicked(info: &PanicHookInfo) -> ! {
+    let mut line = Line::new();
+    let _ = write!(line, "catch-unwind: thread-local destructor panicked");
+    if let Some(msg) = payload_message(info.payload()) {
+        let _ = write!(line, ": {msg}");
+    }
+    if let Some(location) = info.location() {
+        let _ = write!(line, " at {location}");
+    }
+    line.emit();
+    abort()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_builder() {
+        static REPORTED: AtomicUsize = AtomicUsize::new(0);
+
+        let handle = Builder::new()
+            .name("test".into())
+            .policy(DropPolicy::Forget)
+            .reporter(|_: &_| {
+                REPORTED.fetch_add(1, Ordering::Relaxed);
+            })
+            .spawn(|| panic!("test"))
+            .unwrap();
+        assert_eq!(handle.join().unwrap(), None::<()>);
+        assert_eq!(REPORTED.load(Ordering::Relaxed), 1);
+
+        let handle = Builder::new().spawn(|| "success").unwrap();
+        assert_eq!(handle.join().unwrap(), Some("success"));
+    }
+
+    #[test]
+    fn test_builder_catch_policy() {
+        for policy in [CatchPolicy::Ignore, CatchPolicy::Log] {
+            let handle = Builder::new()
+                .catch_policy(policy)
+                .spawn(|| panic!("test"))
+                .unwrap();
+            assert_eq!(handle.join().unwrap(), None::<()>);
+        }
+    }
+
+    #[test]
+    fn test_builder_catch_policy_abort() {
+        if crate::tests::in_child() {
+            let handle = Builder::new()
+                .catch_policy(CatchPolicy::Abort)
+                .spawn(|| panic!("test"))
+                .unwrap();
+            let _ = handle.join();
+            return;
+        }
+        let output = crate::tests::run_in_child("thread::tests::test_builder_catch_policy_abort");
+        crate::tests::assert_aborted(&output);
+    }
+
+    #[test]
+    fn test_default_policy() {
+        let handle = thread::spawn(|| {
+            assert_eq!(inherited_policy(), None);
+            set_default_policy(DropPolicy::Log);
+            Builder::new()
+                .spawn(|| {
+                    assert_eq!(default_policy(), DropPolicy::Log);
+                    crate::catch_unwind_wrapped(crate::tests::endless_panic)
+                        .map_err(drop)
+                        .is_err()
+                })
+                .unwrap()
+                .join()
+                .unwrap()
+        });
+        assert_eq!(handle.join().unwrap(), Some(true));
+    }
+
+    #[test]
+    fn test_with_drop_policy() {
+        set_default_policy(DropPolicy::Log);
+        with_drop_policy(DropPolicy::Forget, || {
+            assert_eq!(default_policy(), DropPolicy::Forget);
+            drop(crate::catch_unwind_wrapped(crate::tests::endless_panic));
+        });
+        assert_eq!(default_policy(), DropPolicy::Log);
+    }
+
+    #[test]
+    fn test_panic_scope() {
+        let mut value = 0;
+        let result = panic_scope(|s| {
+            s.spawn(|| value += 1);
+            s.spawn(|| panic!("test"));
+        });
+        assert_eq!(value, 1);
+        let panics = result.unwrap_err();
+        assert_eq!(panics.messages().collect::<Vec<_>>(), ["test"]);
+
+        let result = panic_scope(|s| {
+            s.spawn(crate::tests::endless_panic);
+            panic!("body")
+        });
+        let panics = result.unwrap_err();
+        assert_eq!(panics.len(), 2);
+        panics.drop_or_forget();
+
+        assert!(matches!(
+            panic_scope(|s| s.spawn(|| 1).join()),
+            Ok(Ok(Some(1)))
+        ));
+    }
+
+    #[test]
+    fn test_run_with_tls_guard() {
+        let handle = thread::spawn(|| run_with_tls_guard(|| "success"));
+        assert_eq!(handle.join().unwrap(), "success");
+    }
+
+    #[test]
+    fn test_run_with_tls_guard_abort() {
+        struct PanicOnDrop;
+
+        impl Drop for PanicOnDrop {
+            fn drop(&mut self) {
+                panic!("tls")
+            }
+        }
+
+        thread_local! {
+            static VALUE: Cell<Option<PanicOnDrop>> = const { Cell::new(None) };
+        }
+
+        if crate::tests::in_child() {
+            let handle = thread::spawn(|| {
+                run_with_tls_guard(|| VALUE.with(|value| value.set(Some(PanicOnDrop))))
+            });
+            let _ = handle.join();
+            return;
+        }
+        let output = crate::tests::run_in_child("thread::tests::test_run_with_tls_guard_abort");
+        crate::tests::assert_aborted(&output);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("thread-local destructor panicked: tls"));
+    }
+}
@@ -0,0 +1,287 @@
+//! Integration with [`tokio`](::tokio) tasks
+
+use crate::{
+    capture::with_capture, future::poll_catch_unwind, supervisor::RestartPolicy,
+    thread::default_policy, CaughtPanic, Payload,
+};
+use ::tokio::{
+    task::{spawn, spawn_blocking, JoinError, JoinHandle},
+    time::sleep,
+};
+use std::{
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Extract the panic payload from a task that panicked.
+///
+/// Returns the original `JoinError` if the task was cancelled instead. Tokio hands the payload
+/// of a panicked task back unprotected, so this conversion should be done before dropping the
+/// error.
+impl TryFrom<JoinError> for Payload {
+    type Error = JoinError;
+
+    #[inline]
+    fn try_from(err: JoinError) -> Result<Self, Self::Error> {
+        err.try_into_panic().map(Payload::new)
+    }
+}
+
+/// Drop a payload on tokio's blocking thread pool instead of the current thread, according to
+/// the current thread's default policy.
+///
+/// The destructor of a payload may block or panic, which shouldn't happen on an async worker
+/// thread. The returned handle can be awaited to wait for the payload to be dropped.
+///
+/// # Panics
+/// Panics if called outside of a tokio runtime.
+#[inline]
+pub fn drop_payload_blocking(payload: Payload) -> JoinHandle<()> {
+    let policy = default_policy();
+    spawn_blocking(move || payload.drop_with_policy(policy))
+}
+
+struct Captured<F> {
+    inner: F,
+}
+
+impl<F: Future> Future for Captured<F> {
+    type Output = Result<F::Output, CaughtPanic>;
+
+    #[inline]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `inner` is structurally pinned
+        let inner = unsafe { self.map_unchecked_mut(|this| &mut this.inner) };
+        with_capture(|| poll_catch_unwind(cx, |cx| inner.poll(cx)))
+            .map(|result| result.map_err(CaughtPanic::new))
+    }
+}
+
+/// Error from awaiting a task spawned with [`spawn_caught`]
+pub enum SpawnError {
+    /// The task panicked
+    Panicked(CaughtPanic),
+
+    /// The task was cancelled, e.g. because the runtime shut down
+    Cancelled(JoinError),
+}
+
+impl SpawnError {
+    /// Get the caught panic, if the task panicked
+    #[inline]
+    pub fn into_panic(self) -> Option<CaughtPanic> {
+        match self {
+            Self::Panicked(panic) => Some(panic),
+            Self::Cancelled(_) => None,
+        }
+    }
+
+    /// Check if the task was cancelled
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, Self::Cancelled(_))
+    }
+}
+
+impl fmt::Debug for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Panicked(panic) => f
+                .debug_tuple("Panicked")
+                .field(&panic.message().unwrap_or("Box<dyn Any>"))
+                .finish(),
+            Self::Cancelled(err) => f.debug_tuple("Cancelled").field(err).finish(),
+        }
+    }
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Panicked(panic) => match panic.message() {
+                Some(msg) => write!(f, "task panicked: {msg}"),
+                None => write!(f, "task panicked"),
+            },
+            Self::Cancelled(_) => write!(f, "task was cancelled"),
+        }
+    }
+}
+
+impl Error for SpawnError {}
+
+/// Handle for awaiting a task spawned with [`spawn_caught`]
+#[must_use = "dropping the handle detaches the task"]
+pub struct CaughtJoinHandle<T> {
+    inner: JoinHandle<Result<T, CaughtPanic>>,
+}
+
+impl<T> CaughtJoinHandle<T> {
+    /// Check if the task has finished
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.inner.is_finished()
+    }
+}
+
+impl<T> Future for CaughtJoinHandle<T> {
+    type Output = Result<T, SpawnError>;
+
+    #[inline]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.inner)
+            .poll(cx)
+            .map(|result| match result {
+                Ok(result) => result.map_err(SpawnError::Panicked),
+                Err(err) => match Payload::try_from(err) {
+                    Ok(payload) => Err(SpawnError::Panicked(CaughtPanic::uncaptured(payload))),
+                    Err(err) => Err(SpawnError::Cancelled(err)),
+                },
+            })
+    }
+}
+
+/// Spawn a task like [`tokio::spawn`](::tokio::spawn), catching any unwinding panics that may
+/// occur while polling it. The location and backtrace of a caught panic are captured, like with
+/// [`catch_unwind_captured`](crate::catch_unwind_captured).
+///
+/// Awaiting the returned handle yields `Ok` if the task completed, `Err(SpawnError::Panicked)`
+/// if a panic was caught, and `Err(SpawnError::Cancelled)` if the task was cancelled, e.g.
+/// because the runtime shut down. The payload is never dropped unprotected by the runtime.
+///
+/// # Panics
+/// Panics if called outside of a tokio runtime.
+#[inline]
+pub fn spawn_caught<F>(fut: F) -> CaughtJoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    CaughtJoinHandle {
+        inner: spawn(Captured { inner: fut }),
+    }
+}
+
+/// Repeatedly await futures produced by `factory` until one of them completes without
+/// panicking, restarting according to `policy`.
+///
+/// Each caught panic is reported to the policy's reporter, if any. When no more restarts are
+/// allowed, the last caught panic is returned instead of being dropped.
+///
+/// # Panics
+/// Panics if called outside of a tokio runtime with the time driver enabled.
+pub async fn supervise_async<F, Fut>(
+    mut factory: F,
+    policy: &RestartPolicy,
+) -> Result<Fut::Output, CaughtPanic>
+where
+    F: FnMut() -> Fut,
+    Fut: Future,
+{
+    let mut restarts = 0;
+    loop {
+        match (Captured { inner: factory() }).await {
+            Ok(ok) => return Ok(ok),
+            Err(panic) => {
+                policy.report(&panic);
+                if !policy.can_restart(restarts) {
+                    return Err(panic);
+                }
+                panic.into_payload().drop_with_policy(default_policy());
+                sleep(policy.backoff_for(restarts)).await;
+                restarts += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::endless_panic;
+    use ::tokio::runtime::Builder;
+    use std::time::Duration;
+
+    #[test]
+    fn test_join_error_into_payload() {
+        let rt = Builder::new_current_thread().build().unwrap();
+        let err = rt
+            .block_on(rt.spawn(async { endless_panic() }))
+            .unwrap_err();
+        Payload::try_from(err).ok().unwrap().drop_or_forget();
+
+        let handle = rt.spawn(std::future::pending::<()>());
+        handle.abort();
+        let err = rt.block_on(handle).unwrap_err();
+        assert!(matches!(Payload::try_from(err), Err(err) if err.is_cancelled()));
+    }
+
+    #[test]
+    fn test_spawn_caught() {
+        let rt = Builder::new_current_thread().build().unwrap();
+        rt.block_on(async {
+            assert!(matches!(
+                spawn_caught(async { "success" }).await,
+                Ok("success")
+            ));
+
+            let err = spawn_caught(async { panic!("test") }).await.unwrap_err();
+            assert!(!err.is_cancelled());
+            let panic = err.into_panic().unwrap();
+            assert_eq!(panic.message(), Some("test"));
+            assert_eq!(panic.location().unwrap().file(), file!());
+        });
+
+        let handle = {
+            let _guard = rt.enter();
+            spawn_caught(std::future::pending::<()>())
+        };
+        drop(rt);
+        let rt = Builder::new_current_thread().build().unwrap();
+        let err = rt.block_on(handle).unwrap_err();
+        assert!(err.is_cancelled());
+    }
+
+    #[test]
+    fn test_supervise_async() {
+        let rt = Builder::new_current_thread().enable_time().build().unwrap();
+        let policy = RestartPolicy::new()
+            .max_restarts(2)
+            .backoff(Duration::ZERO, Duration::ZERO);
+        rt.block_on(async {
+            let mut attempts = 0;
+            let result = supervise_async(
+                || {
+                    attempts += 1;
+                    let attempt = attempts;
+                    async move {
+                        if attempt < 3 {
+                            panic!("attempt {attempt}")
+                        }
+                        attempt
+                    }
+                },
+                &policy,
+            )
+            .await;
+            assert!(matches!(result, Ok(3)));
+
+            let panic = supervise_async(|| async { panic!("test") }, &policy)
+                .await
+                .unwrap_err();
+            assert_eq!(panic.message(), Some("test"));
+        });
+    }
+
+    #[test]
+    fn test_drop_payload_blocking() {
+        let rt = Builder::new_current_thread().build().unwrap();
+        let payload = crate::catch_unwind_wrapped(endless_panic).unwrap_err();
+        rt.block_on(async {
+            crate::thread::set_default_policy(crate::DropPolicy::Forget);
+            drop_payload_blocking(payload).await.unwrap();
+        });
+    }
+}
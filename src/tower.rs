@@ -0,0 +1,219 @@
+//! Catching panics in [`tower`](https://docs.rs/tower) services
+
+use crate::{
+    capture::with_capture, catch_unwind_wrapped, future::poll_catch_unwind,
+    report::report_caught_guarded, thread::default_policy, CaughtPanic, PanicReporter,
+};
+use std::{
+    fmt,
+    future::Future,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer that wraps services in [`CatchPanic`], to catch panics from calling them and from their
+/// response futures.
+///
+/// A caught panic is reported to the configured reporter, if any, and then converted to the
+/// result of the call with the handler, which gets the [`CaughtPanic`] with its location and
+/// backtrace.
+///
+/// ```
+/// use catch_unwind::{tower::CatchPanicLayer, CaughtPanic, StderrReporter};
+///
+/// let layer = CatchPanicLayer::new(|panic: CaughtPanic| -> Result<String, String> {
+///     Err(format!("internal error: {}", panic.message().unwrap_or("panic")))
+/// })
+/// .reporter(StderrReporter);
+/// ```
+#[derive(Clone)]
+pub struct CatchPanicLayer<H> {
+    handler: H,
+    reporter: Option<Arc<dyn PanicReporter>>,
+}
+
+impl<H> CatchPanicLayer<H> {
+    /// Create a new layer that converts caught panics to results with `handler`
+    #[inline]
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            reporter: None,
+        }
+    }
+
+    /// Set the reporter that caught panics are sent to
+    #[inline]
+    pub fn reporter(mut self, reporter: impl PanicReporter + 'static) -> Self {
+        self.reporter = Some(Arc::new(reporter));
+        self
+    }
+}
+
+impl<H> fmt::Debug for CatchPanicLayer<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CatchPanicLayer").finish_non_exhaustive()
+    }
+}
+
+impl<S, H: Clone> Layer<S> for CatchPanicLayer<H> {
+    type Service = CatchPanic<S, H>;
+
+    #[inline]
+    fn layer(&self, inner: S) -> Self::Service {
+        CatchPanic {
+            inner,
+            handler: self.handler.clone(),
+            reporter: self.reporter.clone(),
+        }
+    }
+}
+
+/// Service that catches panics from calling the inner service and from its response futures.
+/// See [`CatchPanicLayer`].
+#[derive(Clone)]
+pub struct CatchPanic<S, H> {
+    inner: S,
+    handler: H,
+    reporter: Option<Arc<dyn PanicReporter>>,
+}
+
+impl<S: fmt::Debug, H> fmt::Debug for CatchPanic<S, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CatchPanic")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, H, Req> Service<Req> for CatchPanic<S, H>
+where
+    S: Service<Req>,
+    H: Fn(CaughtPanic) -> Result<S::Response, S::Error> + Clone,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, H>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        let inner = &mut self.inner;
+        let state =
+            match with_capture(|| catch_unwind_wrapped(AssertUnwindSafe(|| inner.call(req)))) {
+                Ok(fut) => State::Pending(fut),
+                Err(payload) => State::Panicked(Some(CaughtPanic::new(payload))),
+            };
+        ResponseFuture {
+            state,
+            handler: self.handler.clone(),
+            reporter: self.reporter.clone(),
+        }
+    }
+}
+
+enum State<F> {
+    Pending(F),
+    Panicked(Option<CaughtPanic>),
+}
+
+/// Response future of [`CatchPanic`]
+#[must_use = "futures do nothing unless polled"]
+pub struct ResponseFuture<F, H> {
+    state: State<F>,
+    handler: H,
+    reporter: Option<Arc<dyn PanicReporter>>,
+}
+
+impl<F, H> fmt::Debug for ResponseFuture<F, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseFuture").finish_non_exhaustive()
+    }
+}
+
+impl<F, H, T, E> Future for ResponseFuture<F, H>
+where
+    F: Future<Output = Result<T, E>>,
+    H: Fn(CaughtPanic) -> Result<T, E>,
+{
+    type Output = Result<T, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: the future in `state` is structurally pinned, and is never moved out of it.
+        // The other fields aren't pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let panic = match &mut this.state {
+            State::Pending(fut) => {
+                // SAFETY: see above
+                let fut = unsafe { Pin::new_unchecked(fut) };
+                match with_capture(|| poll_catch_unwind(cx, |cx| fut.poll(cx))) {
+                    Poll::Ready(Ok(output)) => return Poll::Ready(output),
+                    Poll::Ready(Err(payload)) => CaughtPanic::new(payload),
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+            State::Panicked(panic) => panic.take().expect("future polled after completion"),
+        };
+        // dropping the pinned future in place is allowed
+        this.state = State::Panicked(None);
+        if let Some(reporter) = &this.reporter {
+            report_caught_guarded(&**reporter, &panic, default_policy());
+        }
+        Poll::Ready((this.handler)(panic))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::{
+        future::ready,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    struct Echo;
+
+    impl Service<u32> for Echo {
+        type Response = u32;
+        type Error = String;
+        type Future = Pin<Box<dyn Future<Output = Result<u32, String>>>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), String>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: u32) -> Self::Future {
+            match req {
+                0 => panic!("call"),
+                1 => Box::pin(async { panic!("response") }),
+                _ => Box::pin(ready(Ok(req))),
+            }
+        }
+    }
+
+    #[test]
+    fn test_catch_panic_layer() {
+        static REPORTED: AtomicUsize = AtomicUsize::new(0);
+
+        let layer = CatchPanicLayer::new(|panic: CaughtPanic| {
+            assert_eq!(panic.location().unwrap().file(), file!());
+            Err(panic.message().unwrap().to_owned())
+        })
+        .reporter(|_: &_| {
+            REPORTED.fetch_add(1, Ordering::Relaxed);
+        });
+        let mut service = layer.layer(Echo);
+        assert_eq!(block_on(service.call(2)), Ok(2));
+        assert_eq!(block_on(service.call(0)), Err("call".into()));
+        assert_eq!(block_on(service.call(1)), Err("response".into()));
+        assert_eq!(REPORTED.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,86 @@
+//! Reporting caught panics to the browser console
+//!
+//! On `wasm32` targets, panics are reported with `console.error`, like
+//! [`console_error_panic_hook`](https://docs.rs/console_error_panic_hook) does for uncaught
+//! panics. On other targets, they're written to stderr instead, so code using this module can
+//! be tested natively.
+//!
+//! Note that panics can only be caught on `wasm32` targets when they're built to unwind, which
+//! isn't the default. See [`can_unwind`](crate::can_unwind).
+
+use crate::{
+    catch_unwind_captured, panic_message, report::report_caught_guarded, CaughtPanic,
+    PanicReporter, Payload, Report,
+};
+use std::{panic::UnwindSafe, thread};
+
+#[cfg(target_arch = "wasm32")]
+mod console {
+    use wasm_bindgen::prelude::wasm_bindgen;
+
+    #[wasm_bindgen]
+    extern "C" {
+        #[wasm_bindgen(js_namespace = console)]
+        pub fn error(msg: &str);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod console {
+    use std::io::{self, Write};
+
+    pub fn error(msg: &str) {
+        let _ = writeln!(io::stderr(), "{msg}");
+    }
+}
+
+/// Reporter that writes caught panics to the browser console with `console.error`.
+///
+/// A panic with captured information is reported with its location and backtrace, formatted
+/// with [`Report`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ConsoleReporter;
+
+impl PanicReporter for ConsoleReporter {
+    fn report(&self, payload: &Payload) {
+        let thread = thread::current();
+        let name = thread.name().unwrap_or("<unnamed>");
+        console::error(&format!(
+            "thread '{name}' panicked: {}",
+            panic_message!(payload)
+        ));
+    }
+
+    fn report_caught(&self, panic: &CaughtPanic) {
+        console::error(&Report::new(panic).to_string());
+    }
+}
+
+/// Invoke the provided closure and catch any unwinding panics that may occur, like
+/// [`catch_unwind_captured`], and report a caught panic to the browser console with
+/// [`ConsoleReporter`]. The caught panic is still returned for handling by the caller.
+///
+/// If reporting panics, that panic is handled according to the current thread's default policy.
+///
+/// Returns `Ok` if no panics were caught and `Err(CaughtPanic)` otherwise.
+///
+/// See [`std::panic::catch_unwind`] for more information.
+#[inline]
+pub fn catch_unwind_console<F: FnOnce() -> R + UnwindSafe, R>(f: F) -> Result<R, CaughtPanic> {
+    catch_unwind_captured(f).inspect_err(|panic| {
+        report_caught_guarded(&ConsoleReporter, panic, crate::thread::default_policy())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catch_unwind_console() {
+        assert!(matches!(catch_unwind_console(|| "success"), Ok("success")));
+        let panic = catch_unwind_console(|| panic!("test")).unwrap_err();
+        assert_eq!(panic.message(), Some("test"));
+        ConsoleReporter.report(panic.payload());
+    }
+}
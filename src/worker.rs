@@ -0,0 +1,121 @@
+//! Running jobs on a dedicated thread with per-job panic isolation
+
+use crate::{
+    catch_unwind_wrapped,
+    thread::{run_with_tls_guard, Builder},
+    Payload,
+};
+use std::{
+    fmt, io,
+    panic::AssertUnwindSafe,
+    sync::mpsc::{self, Receiver, Sender},
+    thread::JoinHandle,
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Handle to a dedicated worker thread that runs submitted jobs in order. Each job is run under
+/// [`catch_unwind_wrapped`], so a panicking job never takes down the worker.
+///
+/// The worker thread is run with [`run_with_tls_guard`], and stops after finishing the
+/// submitted jobs when the handle is dropped.
+///
+/// ```
+/// use catch_unwind::worker::Worker;
+///
+/// let worker = Worker::new().unwrap();
+/// let panicked = worker.submit(|| panic!("oh no"));
+/// let result = worker.submit(|| 1 + 1);
+/// panicked.recv().unwrap().unwrap_err().drop_or_forget();
+/// assert!(matches!(result.recv().unwrap(), Ok(2)));
+/// ```
+pub struct Worker {
+    jobs: Option<Sender<Job>>,
+    thread: Option<JoinHandle<Option<()>>>,
+}
+
+impl fmt::Debug for Worker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Worker").finish_non_exhaustive()
+    }
+}
+
+impl Worker {
+    /// Spawn a new worker thread
+    #[inline]
+    pub fn new() -> io::Result<Self> {
+        Self::with_builder(Builder::new())
+    }
+
+    /// Spawn a new worker thread configured by `builder`. The builder's policy and reporter
+    /// only apply to panics outside of the jobs, which are handled per job.
+    pub fn with_builder(builder: Builder) -> io::Result<Self> {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let thread = builder.spawn(move || {
+            run_with_tls_guard(|| {
+                for job in receiver {
+                    job()
+                }
+            })
+        })?;
+        Ok(Self {
+            jobs: Some(jobs),
+            thread: Some(thread),
+        })
+    }
+
+    /// Submit a job to run on the worker thread after the previously submitted jobs.
+    ///
+    /// Returns a receiver for the outcome of the job, which is `Ok` if the job didn't panic and
+    /// `Err(Payload)` otherwise. If the receiver is dropped first, the outcome is dropped on the
+    /// worker thread.
+    pub fn submit<F, R>(&self, f: F) -> Receiver<Result<R, Payload>>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let job = Box::new(move || {
+            let _ = sender.send(catch_unwind_wrapped(AssertUnwindSafe(f)));
+        });
+        if let Some(jobs) = &self.jobs {
+            let _ = jobs.send(job);
+        }
+        receiver
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.jobs = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::endless_panic;
+
+    #[test]
+    fn test_worker() {
+        let worker = Worker::new().unwrap();
+        let results: Vec<_> = (0..4)
+            .map(|i| {
+                worker.submit(move || {
+                    if i % 2 == 1 {
+                        endless_panic()
+                    }
+                    i
+                })
+            })
+            .collect();
+        let results: Vec<_> = results
+            .into_iter()
+            .map(|result| result.recv().unwrap().map_err(Payload::drop_or_forget))
+            .collect();
+        assert_eq!(results, [Ok(0), Err(()), Ok(2), Err(())]);
+    }
+}